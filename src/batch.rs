@@ -0,0 +1,225 @@
+//! Expands the files, directories and glob patterns given on the command line
+//! into the Markdown files they name, for converting many files at once.
+
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Extensions of the files picked up when searching a directory.
+pub const MARKDOWN_EXTENSIONS: &[&str] = &["md", "markdown"];
+
+/// A Markdown file to convert.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputFile {
+    pub path: PathBuf,
+    /// Where the file sits below the closest directory that holds every file
+    /// found, which is mirrored under an output directory.
+    pub relative: PathBuf,
+}
+
+impl InputFile {
+    /// Returns the path the converted file is written to under `out_dir`.
+    pub fn output_path(&self, out_dir: &Path, extension: &str) -> PathBuf {
+        out_dir.join(&self.relative).with_extension(extension)
+    }
+}
+
+/// Expands each input into the files it names, keeping them in order and
+/// dropping duplicates. Directories are searched recursively for Markdown
+/// files, and inputs containing `*`, `?` or `[` are treated as glob patterns.
+/// Inputs that name nothing are returned alongside the files as errors.
+pub fn expand_inputs<S: AsRef<str>>(inputs: &[S]) -> (Vec<InputFile>, Vec<(String, io::Error)>) {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    let mut errors = Vec::new();
+    for input in inputs {
+        let input = input.as_ref();
+        match expand_input(input) {
+            Ok(found) if found.is_empty() => errors.push((
+                input.to_string(),
+                io::Error::new(io::ErrorKind::NotFound, "no markdown files found"),
+            )),
+            Ok(found) => {
+                for path in found {
+                    if !paths.contains(&path) {
+                        paths.push(path);
+                    }
+                }
+            }
+            Err(e) => errors.push((input.to_string(), e)),
+        }
+    }
+
+    let absolute: Vec<PathBuf> = paths.iter().map(|path| absolute(path)).collect();
+    let base = common_dir(&absolute);
+    let files = paths
+        .into_iter()
+        .zip(absolute)
+        .map(|(path, absolute)| {
+            let below = absolute.strip_prefix(&base).unwrap_or(&absolute);
+            // Only names are kept, so that the file can't land outside an
+            // output directory.
+            let relative = below
+                .components()
+                .filter(|c| matches!(c, Component::Normal(_)))
+                .collect();
+            InputFile { path, relative }
+        })
+        .collect();
+    (files, errors)
+}
+
+fn expand_input(input: &str) -> io::Result<Vec<PathBuf>> {
+    if !is_pattern(input) {
+        let path = Path::new(input);
+        if fs::metadata(path)?.is_dir() {
+            let mut files = Vec::new();
+            find_markdown(path, &mut files)?;
+            return Ok(files);
+        }
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let paths = glob::glob(input)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+    let mut files = Vec::new();
+    for path in paths {
+        let path = path.map_err(io::Error::from)?;
+        if path.is_dir() {
+            find_markdown(&path, &mut files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Makes a path absolute, resolving any symbolic links if it exists.
+pub(crate) fn absolute(path: &Path) -> PathBuf {
+    path.canonicalize()
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Returns the deepest directory that all of the files are under.
+fn common_dir(files: &[PathBuf]) -> PathBuf {
+    let mut dirs = files.iter().map(|file| file.parent().unwrap_or(file));
+    let mut common = match dirs.next() {
+        Some(dir) => dir.to_path_buf(),
+        None => return PathBuf::new(),
+    };
+    for dir in dirs {
+        while !dir.starts_with(&common) {
+            if !common.pop() {
+                return PathBuf::new();
+            }
+        }
+    }
+    common
+}
+
+pub(crate) fn is_pattern(input: &str) -> bool {
+    input.contains(['*', '?', '['])
+}
+
+/// Returns the directory that a glob pattern's matches are under: the
+/// components before the first one with a wildcard.
+pub(crate) fn glob_base(pattern: &str) -> PathBuf {
+    let mut base = PathBuf::new();
+    let mut components = Path::new(pattern).components().peekable();
+    while let Some(component) = components.next() {
+        let wildcard = match component {
+            Component::Normal(name) => is_pattern(&name.to_string_lossy()),
+            _ => false,
+        };
+        // The last component names files rather than a directory.
+        if wildcard || components.peek().is_none() {
+            break;
+        }
+        base.push(component);
+    }
+    base
+}
+
+/// Adds the Markdown files under `dir` to `files`, in a stable order.
+fn find_markdown(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            find_markdown(&path, files)?;
+        } else if is_markdown(&path) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn is_markdown(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| MARKDOWN_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_inputs() {
+        let root = std::env::temp_dir().join(format!("slackify-batch-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("docs/guides")).unwrap();
+        fs::create_dir_all(root.join("other")).unwrap();
+        for file in [
+            "docs/intro.md",
+            "docs/guides/setup.markdown",
+            "docs/notes.txt",
+            "other/intro.md",
+            "a.md",
+        ] {
+            fs::write(root.join(file), "# Title").unwrap();
+        }
+        let dir = root.join("docs").display().to_string();
+        let single = root.join("a.md").display().to_string();
+        let pattern = root.join("*.md").display().to_string();
+        let missing = root.join("missing.md").display().to_string();
+
+        let (files, errors) = expand_inputs(&[dir, single, pattern, missing.clone()]);
+        let relative: Vec<_> = files.iter().map(|f| f.relative.clone()).collect();
+        assert_eq!(
+            relative,
+            vec![
+                PathBuf::from("docs/guides/setup.markdown"),
+                PathBuf::from("docs/intro.md"),
+                PathBuf::from("a.md"),
+            ]
+        );
+        assert_eq!(
+            files[0].output_path(Path::new("out"), "txt"),
+            PathBuf::from("out/docs/guides/setup.txt")
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, missing);
+
+        // Files with the same name keep the directories that tell them apart.
+        let (files, _) = expand_inputs(&[
+            root.join("docs/intro.md").display().to_string(),
+            root.join("other").display().to_string(),
+        ]);
+        let relative: Vec<_> = files.iter().map(|f| f.relative.clone()).collect();
+        assert_eq!(
+            relative,
+            vec![
+                PathBuf::from("docs/intro.md"),
+                PathBuf::from("other/intro.md")
+            ]
+        );
+
+        let (files, _) = expand_inputs(&[root.join("docs/intro.md").display().to_string()]);
+        assert_eq!(files[0].relative, PathBuf::from("intro.md"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
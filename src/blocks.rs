@@ -0,0 +1,481 @@
+//! Block Kit renderer that takes an iterator of events as input.
+//!
+//! Top-level blocks of the document are mapped onto Block Kit blocks, and the
+//! contents of each block are rendered to Slackdown using the `slackdown` module.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use pulldown_cmark::Event::*;
+use pulldown_cmark::{CowStr, Event, Tag};
+use serde_json::{json, Value};
+
+use crate::html::text_content;
+use crate::slackdown::{push_slackdown_numbered, SlackdownOptions};
+use crate::snippet::parse_info;
+use crate::split::{split_block, split_point};
+
+/// Maximum length of the `text` of a `header` block.
+pub const HEADER_TEXT_LIMIT: usize = 150;
+/// Maximum length of the `text` of a `section` block.
+pub const SECTION_TEXT_LIMIT: usize = 3000;
+/// Maximum length of the `alt_text` and `title` of an `image` block.
+pub const IMAGE_TEXT_LIMIT: usize = 2000;
+
+struct BlocksWriter<'a, I> {
+    /// Iterator supplying events.
+    iter: I,
+
+    /// Options used when rendering the text of `section` blocks.
+    options: SlackdownOptions,
+
+    /// Blocks rendered so far.
+    blocks: Vec<Value>,
+
+    /// Events of the top-level block currently being collected.
+    pending: Vec<Event<'a>>,
+
+    /// Footnote numbers, shared by every section so that they are numbered
+    /// consistently across the whole message.
+    numbers: HashMap<CowStr<'a>, usize>,
+    /// Events of the footnote definitions, which are rendered together at the end.
+    footnotes: Vec<Event<'a>>,
+}
+
+impl<'a, I> BlocksWriter<'a, I>
+where
+    I: Iterator<Item = Event<'a>>,
+{
+    fn new(iter: I, options: SlackdownOptions) -> Self {
+        Self {
+            iter,
+            options,
+            blocks: Vec::new(),
+            pending: Vec::new(),
+            numbers: HashMap::new(),
+            footnotes: Vec::new(),
+        }
+    }
+
+    pub fn run(mut self) -> Vec<Value> {
+        while let Some(event) = self.iter.next() {
+            match event {
+                Event::Start(Tag::Header(_)) => {
+                    let text = self.plain_text();
+                    self.push_header(&text);
+                }
+                Event::Start(Tag::Rule) => {
+                    self.skip_block();
+                    self.blocks.push(json!({ "type": "divider" }));
+                }
+                Event::Start(Tag::CodeBlock(info)) => {
+                    if self.options.code_labels {
+                        if let (Some(language), _) = parse_info(&info) {
+                            self.push_context(&format!("_{}_", language));
+                        }
+                    }
+                    let code = self.plain_text();
+                    self.push_preformatted(&code);
+                }
+                Event::Start(Tag::Paragraph) => {
+                    self.pending.push(Event::Start(Tag::Paragraph));
+                    self.collect_block();
+                    if is_images_only(&self.pending) {
+                        self.push_images();
+                    } else {
+                        self.push_section();
+                    }
+                }
+                Event::Start(Tag::FootnoteDefinition(name)) => {
+                    self.pending
+                        .push(Event::Start(Tag::FootnoteDefinition(name)));
+                    self.collect_block();
+                    self.footnotes.append(&mut self.pending);
+                }
+                Event::Start(tag) => {
+                    self.pending.push(Event::Start(tag));
+                    self.collect_block();
+                    self.push_section();
+                }
+                // Everything else is contained in a block, but render stray
+                // events as a section of their own rather than dropping them.
+                event => {
+                    self.pending.push(event);
+                    self.push_section();
+                }
+            }
+        }
+        // Rendering the definitions on their own produces just the "Notes" section.
+        if !self.footnotes.is_empty() {
+            self.pending = std::mem::take(&mut self.footnotes);
+            self.push_section();
+        }
+        self.blocks
+    }
+
+    /// Moves the remaining events of the current block into `pending`,
+    /// including its end tag.
+    fn collect_block(&mut self) {
+        let mut nest = 0;
+        for event in self.iter.by_ref() {
+            match event {
+                Event::Start(_) => nest += 1,
+                Event::End(_) => {
+                    if nest == 0 {
+                        self.pending.push(event);
+                        break;
+                    }
+                    nest -= 1;
+                }
+                _ => (),
+            }
+            self.pending.push(event);
+        }
+    }
+
+    /// Consumes the rest of the current block, including its end tag.
+    fn skip_block(&mut self) {
+        self.collect_block();
+        self.pending.clear();
+    }
+
+    /// Consumes the rest of the current block, returning its text without any
+    /// formatting.
+    fn plain_text(&mut self) -> String {
+        self.collect_block();
+        let mut text = String::new();
+        for event in self.pending.drain(..) {
+            match event {
+                Text(t) | Code(t) => text.push_str(&t),
+                Html(html) | InlineHtml(html) => text.push_str(&text_content(&html)),
+                SoftBreak | HardBreak => text.push(' '),
+                _ => (),
+            }
+        }
+        text
+    }
+
+    fn push_header(&mut self, text: &str) {
+        let text = truncate(text.trim(), HEADER_TEXT_LIMIT);
+        if text.is_empty() {
+            return;
+        }
+        self.blocks.push(json!({
+            "type": "header",
+            "text": { "type": "plain_text", "text": text, "emoji": true },
+        }));
+    }
+
+    fn push_context(&mut self, text: &str) {
+        self.blocks.push(json!({
+            "type": "context",
+            "elements": [{ "type": "mrkdwn", "text": text }],
+        }));
+    }
+
+    fn push_preformatted(&mut self, code: &str) {
+        let code = code.trim_end_matches('\n');
+        if code.is_empty() {
+            return;
+        }
+        for chunk in split_text(code, SECTION_TEXT_LIMIT) {
+            self.blocks.push(json!({
+                "type": "rich_text",
+                "elements": [{
+                    "type": "rich_text_preformatted",
+                    "elements": [{ "type": "text", "text": chunk }],
+                }],
+            }));
+        }
+    }
+
+    /// Adds an `image` block for every image in the pending events.
+    fn push_images(&mut self) {
+        let mut image = None;
+        let mut alt_text = String::new();
+        for event in self.pending.drain(..) {
+            match event {
+                Event::Start(Tag::Image(_, dest, title)) => {
+                    image = Some((dest, title));
+                    alt_text.clear();
+                }
+                Event::End(Tag::Image(_, _, _)) => {
+                    if let Some((dest, title)) = image.take() {
+                        let alt_text = match alt_text.trim() {
+                            "" => "image",
+                            alt => alt,
+                        };
+                        let mut block = json!({
+                            "type": "image",
+                            "image_url": dest.as_ref(),
+                            "alt_text": truncate(alt_text, IMAGE_TEXT_LIMIT),
+                        });
+                        if !title.is_empty() {
+                            block["title"] = json!({
+                                "type": "plain_text",
+                                "text": truncate(&title, IMAGE_TEXT_LIMIT),
+                                "emoji": true,
+                            });
+                        }
+                        self.blocks.push(block);
+                    }
+                }
+                Text(t) | Code(t) if image.is_some() => alt_text.push_str(&t),
+                _ => (),
+            }
+        }
+    }
+
+    /// Renders the pending events to Slackdown and adds them as one or more
+    /// `section` blocks.
+    fn push_section(&mut self) {
+        let mut mrkdwn = String::new();
+        push_slackdown_numbered(
+            &mut mrkdwn,
+            self.pending.drain(..),
+            &self.options,
+            &mut self.numbers,
+        );
+        let mrkdwn = mrkdwn.trim();
+        if mrkdwn.is_empty() {
+            return;
+        }
+        for chunk in split_block(mrkdwn, SECTION_TEXT_LIMIT) {
+            self.blocks.push(json!({
+                "type": "section",
+                "text": { "type": "mrkdwn", "text": chunk },
+            }));
+        }
+    }
+}
+
+/// Whether a paragraph consists of nothing but images, which can then be
+/// rendered as `image` blocks without losing any text. Slack only accepts
+/// images at http(s) URLs, and rejects the whole message otherwise.
+fn is_images_only(events: &[Event]) -> bool {
+    let mut nest = 0;
+    let mut images = 0;
+    for event in events {
+        match event {
+            Event::Start(Tag::Image(_, dest, _)) if !is_http_url(dest) => return false,
+            Event::Start(Tag::Image(_, _, _)) => {
+                nest += 1;
+                images += 1;
+            }
+            Event::End(Tag::Image(_, _, _)) => nest -= 1,
+            Event::Start(Tag::Paragraph) | Event::End(Tag::Paragraph) => (),
+            _ if nest > 0 => (),
+            Text(t) if t.trim().is_empty() => (),
+            SoftBreak | HardBreak => (),
+            _ => return false,
+        }
+    }
+    images > 0
+}
+
+fn is_http_url(url: &str) -> bool {
+    let scheme = url.split_once("://").map_or("", |(scheme, _)| scheme);
+    scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https")
+}
+
+/// Truncates `s` to at most `limit` characters, marking the cut with an ellipsis.
+fn truncate(s: &str, limit: usize) -> String {
+    if s.chars().count() <= limit {
+        return s.to_string();
+    }
+    let mut truncated: String = s.chars().take(limit - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// Splits `s` into chunks of at most `limit` characters, preferring to split
+/// after a newline, and otherwise between words outside of any link or
+/// formatting span.
+fn split_text(s: &str, limit: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut rest = s;
+    while rest.chars().count() > limit {
+        let hard_end = rest
+            .char_indices()
+            .nth(limit)
+            .map(|(i, _)| i)
+            .unwrap_or_else(|| rest.len());
+        let end = match rest[..hard_end].rfind('\n') {
+            Some(i) if i > 0 => i + 1,
+            _ => split_point(rest, limit),
+        };
+        chunks.push(rest[..end].trim_end_matches('\n').to_string());
+        rest = rest[end..].trim_start_matches(' ');
+    }
+    if !rest.is_empty() {
+        chunks.push(rest.to_string());
+    }
+    chunks
+}
+
+/// Renders the events to a Block Kit `blocks` array, appending to `blocks`.
+pub fn push_blocks<'a, I>(blocks: &mut Vec<Value>, iter: I)
+where
+    I: Iterator<Item = Event<'a>>,
+{
+    push_blocks_ext(blocks, iter, &SlackdownOptions::default());
+}
+
+pub fn push_blocks_ext<'a, I>(blocks: &mut Vec<Value>, iter: I, options: &SlackdownOptions)
+where
+    I: Iterator<Item = Event<'a>>,
+{
+    blocks.extend(BlocksWriter::new(iter, options.clone()).run());
+}
+
+/// Renders the events to a Block Kit `blocks` array, writing it as JSON.
+pub fn write_blocks<'a, I, W>(writer: W, iter: I) -> io::Result<()>
+where
+    I: Iterator<Item = Event<'a>>,
+    W: Write,
+{
+    write_blocks_ext(writer, iter, &SlackdownOptions::default())
+}
+
+pub fn write_blocks_ext<'a, I, W>(writer: W, iter: I, options: &SlackdownOptions) -> io::Result<()>
+where
+    I: Iterator<Item = Event<'a>>,
+    W: Write,
+{
+    let blocks = Value::Array(BlocksWriter::new(iter, options.clone()).run());
+    serde_json::to_writer(writer, &blocks).map_err(io::Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pulldown_cmark::{Options, Parser};
+
+    #[test]
+    fn test_document_to_blocks() {
+        let input = "# Release *1.2*
+
+Some **news** & more.
+
+---
+
+```rust
+let x = 1;
+```
+
+- one
+- two";
+        let mut blocks = Vec::new();
+        push_blocks(&mut blocks, Parser::new(input));
+        let expected = json!([
+            { "type": "header", "text": { "type": "plain_text", "text": "Release 1.2", "emoji": true } },
+            { "type": "section", "text": { "type": "mrkdwn", "text": "Some *news* &amp; more." } },
+            { "type": "divider" },
+            {
+                "type": "rich_text",
+                "elements": [{
+                    "type": "rich_text_preformatted",
+                    "elements": [{ "type": "text", "text": "let x = 1;" }],
+                }],
+            },
+            { "type": "section", "text": { "type": "mrkdwn", "text": "• one\n• two" } },
+        ]);
+        assert_eq!(Value::Array(blocks), expected);
+    }
+
+    #[test]
+    fn test_images_to_blocks() {
+        let input = "![A *cat*](https://example.com/cat.png \"Cat\")
+
+Inline ![dog](https://example.com/dog.png) picture
+
+![logo](docs/logo.png)";
+        let mut blocks = Vec::new();
+        let options = SlackdownOptions {
+            image_prefix: ":frame_with_picture: ".to_string(),
+            ..SlackdownOptions::default()
+        };
+        push_blocks_ext(&mut blocks, Parser::new(input), &options);
+        let expected = json!([
+            {
+                "type": "image",
+                "image_url": "https://example.com/cat.png",
+                "alt_text": "A cat",
+                "title": { "type": "plain_text", "text": "Cat", "emoji": true },
+            },
+            {
+                "type": "section",
+                "text": {
+                    "type": "mrkdwn",
+                    "text": "Inline <https://example.com/dog.png|:frame_with_picture: dog> picture",
+                },
+            },
+            {
+                "type": "section",
+                "text": { "type": "mrkdwn", "text": ":frame_with_picture: logo" },
+            },
+        ]);
+        assert_eq!(Value::Array(blocks), expected);
+    }
+
+    #[test]
+    fn test_footnotes_in_blocks() {
+        let input = "First[^a].
+
+[^b]: Bee.
+
+Second[^b].
+
+[^a]: Ay.";
+        let mut blocks = Vec::new();
+        push_blocks(
+            &mut blocks,
+            Parser::new_ext(input, Options::ENABLE_FOOTNOTES),
+        );
+        let texts: Vec<&str> = blocks
+            .iter()
+            .map(|b| b["text"]["text"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            texts,
+            vec!["First[1].", "Second[2].", "*Notes*\n[1] Ay.\n[2] Bee."]
+        );
+    }
+
+    #[test]
+    fn test_long_sections_keep_code_fences() {
+        let mut input = "| a | b |\n|---|---|\n".to_string();
+        for row in 0..300 {
+            input.push_str(&format!("| row {} | value {} |\n", row, row));
+        }
+        let mut blocks = Vec::new();
+        push_blocks(&mut blocks, Parser::new_ext(&input, Options::ENABLE_TABLES));
+        assert!(blocks.len() > 1);
+        for block in &blocks {
+            let text = block["text"]["text"].as_str().unwrap();
+            assert!(text.chars().count() <= SECTION_TEXT_LIMIT);
+            assert!(text.starts_with("```") && text.ends_with("```"), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_split_text_prefers_newlines() {
+        let chunks = split_text("aaaa\nbbbb\ncc", 7);
+        assert_eq!(chunks, vec!["aaaa", "bbbb\ncc"]);
+
+        let chunks = split_text("aaaaaaaaaa", 4);
+        assert_eq!(chunks, vec!["aaaa", "aaaa", "aa"]);
+
+        let chunks = split_text("Read *the* <https://example.com/a/b|the docs> now", 40);
+        assert_eq!(
+            chunks,
+            vec!["Read *the*", "<https://example.com/a/b|the docs> now"]
+        );
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("much too long", 5), "much…");
+    }
+}
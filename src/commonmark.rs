@@ -0,0 +1,350 @@
+//! Converts Slack's mrkdwn back into CommonMark, the reverse of the `slackdown` module.
+//!
+//! Slack messages have no block structure beyond code blocks, quotes and
+//! bullets, so the conversion works line by line, treating every line break
+//! in the message as a hard line break.
+
+/// Characters that are given a meaning by CommonMark but not by mrkdwn, and so
+/// must be backslash-escaped when they appear in text.
+const COMMONMARK_SPECIAL: &[char] = &['\\', '*', '_', '`', '[', ']', '<', '~'];
+
+/// Bullets that Slack users, and this crate's own `RenderStyle`, use for lists.
+const BULLETS: &[char] = &['•', '◦', '▪', '-'];
+
+/// The kind of a converted line, used to decide how it joins onto the next.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Line {
+    Blank,
+    Text,
+    Quote,
+    Item,
+}
+
+/// Converts mrkdwn into CommonMark, appending to `s`.
+pub fn push_commonmark(s: &mut String, mrkdwn: &str) {
+    let mut lines: Vec<(Line, String)> = Vec::new();
+    let mut rest = mrkdwn;
+    while let Some(start) = rest.find("```") {
+        let after = &rest[start + 3..];
+        let end = match after.find("```") {
+            Some(end) => end,
+            // An unterminated fence is just text.
+            None => break,
+        };
+        let text = &rest[..start];
+        push_lines(&mut lines, text.strip_suffix('\n').unwrap_or(text));
+        let code = decode_entities(after[..end].trim_start_matches('\n').trim_end_matches('\n'));
+        lines.push((Line::Blank, "```".to_string()));
+        for line in code.lines() {
+            lines.push((Line::Blank, line.to_string()));
+        }
+        lines.push((Line::Blank, "```".to_string()));
+        rest = &after[end + 3..];
+        if rest.starts_with('\n') {
+            rest = &rest[1..];
+        }
+    }
+    push_lines(&mut lines, rest);
+
+    for (i, (kind, line)) in lines.iter().enumerate() {
+        s.push_str(line);
+        // Every line break in a Slack message is a hard break, while CommonMark
+        // joins lines of a paragraph.
+        // A blank line, quoted or not, ends the paragraph instead.
+        let next = lines.get(i + 1).filter(|(_, line)| !is_blank(line));
+        let hard_break = matches!(
+            (kind, next),
+            (Line::Text, Some((Line::Text, _))) | (Line::Quote, Some((Line::Quote, _)))
+        );
+        if hard_break && !is_blank(line) {
+            s.push('\\');
+        }
+        if i + 1 < lines.len() {
+            s.push('\n');
+        }
+    }
+}
+
+/// Whether a line is empty but for any quote marker.
+fn is_blank(line: &str) -> bool {
+    line.trim_end().trim_start_matches('>').trim().is_empty()
+}
+
+/// Converts the lines of `text`, which contains no code blocks.
+fn push_lines(lines: &mut Vec<(Line, String)>, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    let mut quoted = false;
+    for line in text.split('\n') {
+        // `>>>` quotes everything that follows it.
+        if let Some(content) = strip_quote(line, ">>>") {
+            quoted = true;
+            lines.push((Line::Quote, quote(&convert_line(content))));
+            continue;
+        }
+        if let Some(content) = strip_quote(line, ">") {
+            lines.push((Line::Quote, quote(&convert_line(content))));
+            continue;
+        }
+        let (kind, converted) = convert_block_line(line);
+        if quoted {
+            let kind = if kind == Line::Item {
+                kind
+            } else {
+                Line::Quote
+            };
+            lines.push((kind, quote(&converted)));
+        } else {
+            lines.push((kind, converted));
+        }
+    }
+}
+
+/// Quotes a converted line, leaving a blank one as a bare `>`.
+fn quote(line: &str) -> String {
+    format!("> {}", line).trim_end().to_string()
+}
+
+/// Returns the content of a quote line, which Slack may have sent with its
+/// `>` escaped as `&gt;`.
+fn strip_quote<'a>(line: &'a str, marker: &str) -> Option<&'a str> {
+    let escaped = marker.replace('>', "&gt;");
+    let content = line
+        .strip_prefix(marker)
+        .or_else(|| line.strip_prefix(escaped.as_str()))?;
+    Some(content.strip_prefix(' ').unwrap_or(content))
+}
+
+/// Converts a line that isn't quoted, recognizing list items.
+fn convert_block_line(line: &str) -> (Line, String) {
+    if line.trim().is_empty() {
+        return (Line::Blank, String::new());
+    }
+    let content = line.trim_start_matches([' ', '\u{a0}', '\t']);
+    let indent = " ".repeat(line.chars().count() - content.chars().count());
+
+    let mut chars = content.chars();
+    if let Some(bullet) = chars.next() {
+        let item = chars.as_str();
+        if BULLETS.contains(&bullet) && item.starts_with(' ') {
+            return (
+                Line::Item,
+                format!("{}- {}", indent, convert_line(item.trim_start())),
+            );
+        }
+    }
+    let digits = content.chars().take_while(char::is_ascii_digit).count();
+    if digits > 0 && content[digits..].starts_with(". ") {
+        return (
+            Line::Item,
+            format!(
+                "{}{}. {}",
+                indent,
+                &content[..digits],
+                convert_line(&content[digits + 2..])
+            ),
+        );
+    }
+
+    let mut converted = convert_line(content);
+    // Text that CommonMark would read as the start of a block.
+    if converted.starts_with('#') || converted.starts_with('>') || converted.starts_with('+') {
+        converted.insert(0, '\\');
+    }
+    (Line::Text, converted)
+}
+
+/// Converts the inline formatting of a single line.
+fn convert_line(line: &str) -> String {
+    let mut out = String::new();
+    let chars: Vec<(usize, char)> = line.char_indices().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let (pos, c) = chars[i];
+        match c {
+            '`' => {
+                if let Some(len) = line[pos + 1..].find('`') {
+                    let code = &line[pos + 1..pos + 1 + len];
+                    out.push('`');
+                    out.push_str(&decode_entities(code));
+                    out.push('`');
+                    i = index_at(&chars, pos + 1 + len + 1);
+                    continue;
+                }
+            }
+            '<' => {
+                if let Some(len) = line[pos + 1..].find('>') {
+                    out.push_str(&convert_entity(&line[pos + 1..pos + 1 + len]));
+                    i = index_at(&chars, pos + 1 + len + 1);
+                    continue;
+                }
+            }
+            '*' | '_' | '~' if opens(&chars, i) => {
+                if let Some(close) = find_close(&chars, i) {
+                    let inner = convert_line(&line[pos + 1..chars[close].0]);
+                    let marker = match c {
+                        '*' => "**",
+                        '_' => "_",
+                        _ => "~~",
+                    };
+                    out.push_str(marker);
+                    out.push_str(&inner);
+                    out.push_str(marker);
+                    i = close + 1;
+                    continue;
+                }
+            }
+            '&' => {
+                if let Some((decoded, len)) = entity_at(&line[pos..]) {
+                    push_escaped(&mut out, decoded);
+                    i = index_at(&chars, pos + len);
+                    continue;
+                }
+            }
+            _ => (),
+        }
+        push_escaped(&mut out, c);
+        i += 1;
+    }
+    out
+}
+
+/// Converts the contents of a `<...>` Slack entity.
+fn convert_entity(entity: &str) -> String {
+    let (target, label) = match entity.find('|') {
+        Some(i) => (&entity[..i], Some(&entity[i + 1..])),
+        None => (entity, None),
+    };
+    let label = label.map(convert_line);
+
+    if let Some(user) = target.strip_prefix('@') {
+        return match label {
+            Some(label) => format!("@{}", label.trim_start_matches('@')),
+            None => format!("@{}", user),
+        };
+    }
+    if let Some(channel) = target.strip_prefix('#') {
+        return format!("#{}", label.unwrap_or_else(|| channel.to_string()));
+    }
+    if let Some(special) = target.strip_prefix('!') {
+        // `<!here>` and friends, or `<!date^...|fallback>`.
+        return match label {
+            Some(label) => label,
+            None => format!("@{}", special),
+        };
+    }
+
+    let url = decode_entities(target);
+    match label {
+        Some(label) if label != convert_line(target) => format!("[{}]({})", label, url),
+        _ if url.contains("://") || url.starts_with("mailto:") => format!("<{}>", url),
+        _ => convert_line(target),
+    }
+}
+
+/// Whether the marker at `i` can open a formatting span: it must not be
+/// preceded by a word character nor followed by whitespace.
+fn opens(chars: &[(usize, char)], i: usize) -> bool {
+    let before_ok = i == 0 || !chars[i - 1].1.is_alphanumeric();
+    let after_ok = chars.get(i + 1).is_some_and(|(_, c)| !c.is_whitespace());
+    before_ok && after_ok
+}
+
+/// Finds the marker closing the span opened at `open`, which must not be
+/// preceded by whitespace nor followed by a word character.
+fn find_close(chars: &[(usize, char)], open: usize) -> Option<usize> {
+    let marker = chars[open].1;
+    (open + 2..chars.len()).find(|&j| {
+        chars[j].1 == marker
+            && !chars[j - 1].1.is_whitespace()
+            && chars.get(j + 1).is_none_or(|(_, c)| !c.is_alphanumeric())
+    })
+}
+
+/// Index into `chars` of the character at byte offset `pos`.
+fn index_at(chars: &[(usize, char)], pos: usize) -> usize {
+    chars
+        .iter()
+        .position(|&(p, _)| p >= pos)
+        .unwrap_or(chars.len())
+}
+
+/// Decodes the entity at the start of `s`, returning the character and the
+/// entity's length.
+fn entity_at(s: &str) -> Option<(char, usize)> {
+    [("&amp;", '&'), ("&lt;", '<'), ("&gt;", '>')]
+        .iter()
+        .find(|(entity, _)| s.starts_with(entity))
+        .map(|&(entity, c)| (c, entity.len()))
+}
+
+fn decode_entities(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+fn push_escaped(out: &mut String, c: char) {
+    if COMMONMARK_SPECIAL.contains(&c) {
+        out.push('\\');
+    }
+    out.push(c);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_commonmark(mrkdwn: &str) -> String {
+        let mut s = String::new();
+        push_commonmark(&mut s, mrkdwn);
+        s
+    }
+
+    #[test]
+    fn test_inline_formatting() {
+        let actual =
+            to_commonmark("*Bold*, _italic_, ~gone~ and `a &lt; b` in 2*3*4 &amp; snake_case_name");
+        let expected =
+            "**Bold**, _italic_, ~~gone~~ and `a < b` in 2\\*3\\*4 & snake\\_case\\_name";
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_entities() {
+        let actual = to_commonmark(
+            "See <https://example.com/a?b=1&amp;c=2|the *docs*>, <https://x.io>, <@U123>, <@U456|alice>, <#C789|deploys> and <!here>",
+        );
+        let expected = "See [the **docs**](https://example.com/a?b=1&c=2), <https://x.io>, @U123, @alice, #deploys and @here";
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_blocks() {
+        let actual = to_commonmark(
+            "*Release*\nFirst line\nsecond line\n&gt; quoted\n&gt; more\n• one\n    ◦ nested\n```\nlet x = a &amp;&amp; b;\n```\nDone",
+        );
+        let expected = "**Release**\\
+First line\\
+second line
+> quoted\\
+> more
+- one
+    - nested
+```
+let x = a && b;
+```
+Done";
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_blank_quote_lines() {
+        let actual = to_commonmark("&gt;&gt;&gt; quoted\nmore\n\nstill");
+        assert_eq!(actual, "> quoted\\\n> more\n>\n> still");
+
+        let actual = to_commonmark("&gt; a\n&gt;\n&gt; b");
+        assert_eq!(actual, "> a\n>\n> b");
+    }
+}
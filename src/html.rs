@@ -0,0 +1,285 @@
+//! A small tokenizer for the HTML that can appear in Markdown, so that common
+//! tags can be converted into Slack formatting.
+
+use std::str::FromStr;
+
+/// How HTML in Markdown is rendered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HtmlPolicy {
+    /// Convert common tags, like `<b>` and `<a href>`, into their Slack
+    /// equivalents, drop other known tags like `<div>`, and show unknown ones
+    /// as text.
+    #[default]
+    Convert,
+    /// Strip all tags, keeping only their text.
+    Strip,
+    /// Show the HTML as text.
+    Escape,
+    /// Write the HTML out as is, for Slack to make of it what it will.
+    Passthrough,
+}
+
+impl FromStr for HtmlPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "convert" => Ok(HtmlPolicy::Convert),
+            "strip" => Ok(HtmlPolicy::Strip),
+            "escape" => Ok(HtmlPolicy::Escape),
+            "passthrough" => Ok(HtmlPolicy::Passthrough),
+            _ => Err(format!(
+                "unknown HTML policy '{}', expected one of convert, strip, escape or passthrough",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Token<'h> {
+    /// An opening or self-closing tag, with its lowercased name and attributes.
+    Start {
+        name: String,
+        attrs: Vec<(String, String)>,
+    },
+    /// A closing tag, with its lowercased name.
+    End { name: String },
+    /// Text between tags, with any character references still encoded.
+    Text(&'h str),
+    /// A comment, doctype or processing instruction.
+    Comment,
+}
+
+impl Token<'_> {
+    pub(crate) fn attr(&self, attr: &str) -> Option<&str> {
+        match self {
+            Token::Start { attrs, .. } => attrs
+                .iter()
+                .find(|(name, _)| name == attr)
+                .map(|(_, value)| value.as_str()),
+            _ => None,
+        }
+    }
+}
+
+/// Splits a fragment of HTML into tokens, each paired with its source. Anything
+/// that doesn't look like a tag is treated as text, so this never fails.
+pub(crate) fn tokenize(html: &str) -> Vec<(Token<'_>, &str)> {
+    let mut tokens = Vec::new();
+    let mut rest = html;
+    while !rest.is_empty() {
+        let lt = match rest.find('<') {
+            Some(lt) => lt,
+            None => {
+                tokens.push((Token::Text(rest), rest));
+                break;
+            }
+        };
+        if lt > 0 {
+            tokens.push((Token::Text(&rest[..lt]), &rest[..lt]));
+            rest = &rest[lt..];
+        }
+        let len = match parse_tag(rest) {
+            Some((token, len)) => {
+                tokens.push((token, &rest[..len]));
+                len
+            }
+            None => {
+                tokens.push((Token::Text(&rest[..1]), &rest[..1]));
+                1
+            }
+        };
+        rest = &rest[len..];
+    }
+    tokens
+}
+
+/// Parses the tag at the start of `s`, returning it and its length.
+fn parse_tag(s: &str) -> Option<(Token<'_>, usize)> {
+    if s.starts_with("<!--") {
+        let end = s.find("-->").map_or(s.len(), |i| i + 3);
+        return Some((Token::Comment, end));
+    }
+    // Other `<!...>` declarations are left as text, since Slack gives meaning
+    // to sequences like `<!here>`.
+    let doctype = s
+        .get(..9)
+        .is_some_and(|d| d.eq_ignore_ascii_case("<!doctype"));
+    if doctype || s.starts_with("<?") {
+        let end = s.find('>')? + 1;
+        return Some((Token::Comment, end));
+    }
+    if s.starts_with("<!") {
+        // Kept whole so that it can be escaped or broken up in one piece.
+        let end = s.find('>')? + 1;
+        return Some((Token::Text(&s[..end]), end));
+    }
+
+    let closing = s.starts_with("</");
+    let body = &s[if closing { 2 } else { 1 }..];
+    let name_len = body
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+        .unwrap_or(body.len());
+    if name_len == 0 || !body.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    let name = body[..name_len].to_ascii_lowercase();
+    let end = tag_end(&body[name_len..])?;
+    let len = s.len() - body.len() + name_len + end + 1;
+
+    if closing {
+        return Some((Token::End { name }, len));
+    }
+    let attrs = parse_attrs(body[name_len..name_len + end].trim_end_matches('/'));
+    Some((Token::Start { name, attrs }, len))
+}
+
+/// Finds the `>` that ends a tag, skipping over any in quoted attribute values.
+fn tag_end(s: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => (),
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '>') => return Some(i),
+            (None, '<') => return None,
+            _ => (),
+        }
+    }
+    None
+}
+
+fn parse_attrs(mut s: &str) -> Vec<(String, String)> {
+    let mut attrs = Vec::new();
+    loop {
+        s = s.trim_start();
+        let name_len = s
+            .find(|c: char| c.is_whitespace() || c == '=')
+            .unwrap_or(s.len());
+        if name_len == 0 {
+            break;
+        }
+        let name = s[..name_len].to_ascii_lowercase();
+        s = s[name_len..].trim_start();
+
+        let mut value = String::new();
+        if let Some(after_eq) = s.strip_prefix('=') {
+            s = after_eq.trim_start();
+            let (v, rest) = match s.chars().next() {
+                Some(q @ '"') | Some(q @ '\'') => {
+                    let end = s[1..].find(q).map_or(s.len(), |i| i + 1);
+                    (&s[1..end], s.get(end + 1..).unwrap_or(""))
+                }
+                _ => {
+                    let end = s.find(char::is_whitespace).unwrap_or(s.len());
+                    (&s[..end], &s[end..])
+                }
+            };
+            value = decode_entities(v);
+            s = rest;
+        }
+        attrs.push((name, value));
+    }
+    attrs
+}
+
+/// Tags that start a new line.
+#[rustfmt::skip]
+const BLOCK_TAGS: &[&str] = &[
+    "address", "article", "aside", "blockquote", "center", "dd", "details", "div", "dl", "dt",
+    "figcaption", "figure", "footer", "h1", "h2", "h3", "h4", "h5", "h6", "header", "hr", "li",
+    "main", "nav", "ol", "p", "pre", "section", "summary", "table", "tr", "ul",
+];
+
+/// Inline tags that are converted, or whose text is kept with no formatting of
+/// its own.
+#[rustfmt::skip]
+const INLINE_TAGS: &[&str] = &[
+    "a", "abbr", "b", "br", "cite", "code", "del", "em", "i", "img", "ins", "kbd", "mark", "s",
+    "samp", "small", "span", "strike", "strong", "sub", "sup", "tbody", "td", "tfoot", "th",
+    "thead", "tt", "u", "var",
+];
+
+pub(crate) fn is_block_tag(name: &str) -> bool {
+    BLOCK_TAGS.contains(&name)
+}
+
+/// Whether a tag is one that is understood, rather than shown as text.
+pub(crate) fn is_known_tag(name: &str) -> bool {
+    is_block_tag(name) || INLINE_TAGS.contains(&name)
+}
+
+/// Returns the text of a fragment of HTML, keeping any unknown tags as text.
+pub(crate) fn text_content(html: &str) -> String {
+    let mut text = String::new();
+    for (token, raw) in tokenize(html) {
+        match token {
+            Token::Text(t) => text.push_str(&decode_entities(t)),
+            Token::Start { name, .. } | Token::End { name } if !is_known_tag(&name) => {
+                text.push_str(raw)
+            }
+            _ => (),
+        }
+    }
+    text
+}
+
+/// Decodes the most common character references.
+pub(crate) fn decode_entities(s: &str) -> String {
+    if !s.contains('&') {
+        return s.to_string();
+    }
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", "\u{a0}")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start(name: &str, attrs: &[(&str, &str)]) -> Token<'static> {
+        Token::Start {
+            name: name.to_string(),
+            attrs: attrs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let tokens: Vec<Token> = tokenize(
+            "<!-- note --><A HREF=\"https://x.io/?a=1&amp;b=2\" title='a > b'>x</a><br/> 1 < 2 <!here>",
+        )
+        .into_iter()
+        .map(|(token, _)| token)
+        .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Comment,
+                start(
+                    "a",
+                    &[("href", "https://x.io/?a=1&b=2"), ("title", "a > b")]
+                ),
+                Token::Text("x"),
+                Token::End {
+                    name: "a".to_string()
+                },
+                start("br", &[]),
+                Token::Text(" 1 "),
+                Token::Text("<"),
+                Token::Text(" 2 "),
+                Token::Text("<!here>"),
+            ]
+        );
+    }
+}
@@ -1,3 +1,289 @@
+//! Convert Markdown into Slack's mrkdwn ("Slackdown") or Block Kit blocks, and
+//! mrkdwn back into Markdown.
+//!
+//! ```
+//! let slacked = slackify_markdown::convert("Some **bold** [news](https://example.com)");
+//! assert_eq!(slacked, "Some *bold* <https://example.com|news>");
+//! ```
+
+#[cfg(feature = "cli")]
+pub mod batch;
+pub mod blocks;
+pub mod commonmark;
+pub mod html;
+pub mod mentions;
+#[cfg(feature = "cli")]
+pub mod server;
 pub mod slackdown;
+pub mod snippet;
+pub mod split;
+pub mod style;
+#[cfg(feature = "cli")]
+pub mod watch;
+#[cfg(feature = "cli")]
+pub mod webhook;
 
 mod escape;
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use pulldown_cmark::{Options, Parser};
+use serde_json::{json, Value};
+
+use crate::html::HtmlPolicy;
+use crate::mentions::{BroadcastPolicy, MentionResolver};
+use crate::slackdown::{FootnoteStyle, SlackdownOptions};
+use crate::snippet::Snippet;
+use crate::style::RenderStyle;
+
+/// What Markdown is converted into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// A single mrkdwn string.
+    #[default]
+    Mrkdwn,
+    /// A Block Kit `blocks` array, serialized as JSON.
+    Blocks,
+    /// A `chat.postMessage` or incoming webhook body, serialized as JSON, with
+    /// both the `blocks` and the mrkdwn `text` that notifications fall back on.
+    Payload,
+    /// Text with all formatting left out.
+    Plain,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mrkdwn" => Ok(OutputFormat::Mrkdwn),
+            "blocks" => Ok(OutputFormat::Blocks),
+            "payload" => Ok(OutputFormat::Payload),
+            "plain" => Ok(OutputFormat::Plain),
+            _ => Err(format!(
+                "unknown format '{}', expected one of mrkdwn, blocks, payload or plain",
+                s
+            )),
+        }
+    }
+}
+
+/// Everything that controls a conversion.
+#[derive(Debug, Clone)]
+pub struct SlackifyOptions {
+    /// Markdown extensions enabled in the parser.
+    pub extensions: Options,
+    pub format: OutputFormat,
+    /// Knobs for rendering mrkdwn, also used for the text of blocks.
+    pub slackdown: SlackdownOptions,
+}
+
+impl Default for SlackifyOptions {
+    fn default() -> Self {
+        let mut extensions = Options::empty();
+        extensions.insert(Options::ENABLE_STRIKETHROUGH);
+        extensions.insert(Options::ENABLE_TASKLISTS);
+        extensions.insert(Options::ENABLE_TABLES);
+        extensions.insert(Options::ENABLE_FOOTNOTES);
+        Self {
+            extensions,
+            format: OutputFormat::default(),
+            slackdown: SlackdownOptions::default(),
+        }
+    }
+}
+
+/// Converts Markdown using a set of `SlackifyOptions`, which can be built up
+/// with the builder methods.
+///
+/// ```
+/// use slackify_markdown::{Converter, OutputFormat};
+///
+/// let converter = Converter::new().tables(false).format(OutputFormat::Blocks);
+/// let json = converter.convert("# Title");
+/// assert!(json.contains("\"type\":\"header\""));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Converter {
+    options: SlackifyOptions,
+}
+
+impl Converter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_options(options: SlackifyOptions) -> Self {
+        Self { options }
+    }
+
+    pub fn options(&self) -> &SlackifyOptions {
+        &self.options
+    }
+
+    fn extension(mut self, extension: Options, enabled: bool) -> Self {
+        self.options.extensions.set(extension, enabled);
+        self
+    }
+
+    pub fn strikethrough(self, enabled: bool) -> Self {
+        self.extension(Options::ENABLE_STRIKETHROUGH, enabled)
+    }
+
+    pub fn tasklists(self, enabled: bool) -> Self {
+        self.extension(Options::ENABLE_TASKLISTS, enabled)
+    }
+
+    pub fn tables(self, enabled: bool) -> Self {
+        self.extension(Options::ENABLE_TABLES, enabled)
+    }
+
+    pub fn footnotes(self, enabled: bool) -> Self {
+        self.extension(Options::ENABLE_FOOTNOTES, enabled)
+    }
+
+    pub fn format(mut self, format: OutputFormat) -> Self {
+        self.options.format = format;
+        self
+    }
+
+    pub fn image_prefix(mut self, prefix: &str) -> Self {
+        self.options.slackdown.image_prefix = prefix.to_string();
+        self
+    }
+
+    pub fn footnote_style(mut self, style: FootnoteStyle) -> Self {
+        self.options.slackdown.footnote_style = style;
+        self
+    }
+
+    /// Sets the glyphs that replace the bullets of checked and unchecked task list items.
+    pub fn task_glyphs(mut self, checked: &str, unchecked: &str) -> Self {
+        self.options.slackdown.task_checked = checked.to_string();
+        self.options.slackdown.task_unchecked = unchecked.to_string();
+        self
+    }
+
+    pub fn style(mut self, style: RenderStyle) -> Self {
+        self.options.slackdown.style = style;
+        self
+    }
+
+    /// Resolves `@user` and `#channel` handles into Slack mentions.
+    pub fn mentions(mut self, resolver: Arc<dyn MentionResolver>) -> Self {
+        self.options.slackdown.mentions = Some(resolver);
+        self
+    }
+
+    pub fn broadcast(mut self, policy: BroadcastPolicy) -> Self {
+        self.options.slackdown.broadcast = policy;
+        self
+    }
+
+    pub fn html(mut self, policy: HtmlPolicy) -> Self {
+        self.options.slackdown.html = policy;
+        self
+    }
+
+    /// Joins the soft-wrapped lines of paragraphs.
+    pub fn reflow(mut self, enabled: bool) -> Self {
+        self.options.slackdown.reflow = enabled;
+        self
+    }
+
+    /// Sets the line written for a horizontal rule, such as `":wavy_dash:"`.
+    pub fn divider(mut self, divider: &str) -> Self {
+        self.options.slackdown.divider = divider.to_string();
+        self
+    }
+
+    /// Labels code blocks with their language.
+    pub fn code_labels(mut self, enabled: bool) -> Self {
+        self.options.slackdown.code_labels = enabled;
+        self
+    }
+
+    /// Takes code blocks of more than `max_lines` lines out of the message as
+    /// snippets, which `convert_with_snippets` returns. Every other conversion
+    /// keeps the code in the message.
+    pub fn snippet_threshold(mut self, max_lines: usize) -> Self {
+        self.options.slackdown.snippet_threshold = Some(max_lines);
+        self
+    }
+
+    fn parser<'a>(&self, markdown: &'a str) -> Parser<'a> {
+        Parser::new_ext(markdown, self.options.extensions)
+    }
+
+    /// Converts Markdown into the configured output format.
+    pub fn convert(&self, markdown: &str) -> String {
+        match self.options.format {
+            OutputFormat::Mrkdwn => self.convert_to_mrkdwn(markdown),
+            OutputFormat::Blocks => Value::Array(self.convert_to_blocks(markdown)).to_string(),
+            OutputFormat::Payload => self.convert_to_payload(markdown).to_string(),
+            OutputFormat::Plain => self.convert_to_plain(markdown),
+        }
+    }
+
+    /// Converts Markdown into mrkdwn, regardless of the configured output format.
+    pub fn convert_to_mrkdwn(&self, markdown: &str) -> String {
+        let mut output = String::new();
+        slackdown::push_slackdown_ext(&mut output, self.parser(markdown), &self.options.slackdown);
+        output
+    }
+
+    /// Converts Markdown into text with no formatting, regardless of the
+    /// configured output format.
+    pub fn convert_to_plain(&self, markdown: &str) -> String {
+        let mut output = String::new();
+        slackdown::push_plain_ext(&mut output, self.parser(markdown), &self.options.slackdown);
+        output
+    }
+
+    /// Converts Markdown into mrkdwn, along with the code blocks taken out of it
+    /// as snippets, which the mrkdwn refers to by number.
+    pub fn convert_with_snippets(&self, markdown: &str) -> (String, Vec<Snippet>) {
+        let mut output = String::new();
+        let snippets = slackdown::push_slackdown_snippets(
+            &mut output,
+            self.parser(markdown),
+            &self.options.slackdown,
+        );
+        (output, snippets)
+    }
+
+    /// Converts Markdown into mrkdwn split into messages of at most `budget`
+    /// characters, such as `split::DEFAULT_CHUNK_BUDGET`.
+    pub fn convert_to_chunks(&self, markdown: &str, budget: usize) -> Vec<String> {
+        split::split_slackdown(self.parser(markdown), &self.options.slackdown, budget)
+    }
+
+    /// Converts Markdown into Block Kit blocks, regardless of the configured output format.
+    pub fn convert_to_blocks(&self, markdown: &str) -> Vec<Value> {
+        let mut output = Vec::new();
+        blocks::push_blocks_ext(&mut output, self.parser(markdown), &self.options.slackdown);
+        output
+    }
+
+    /// Converts Markdown into a message body for `chat.postMessage` or an
+    /// incoming webhook, with `text` and `blocks`.
+    pub fn convert_to_payload(&self, markdown: &str) -> Value {
+        json!({
+            "text": self.convert_to_mrkdwn(markdown),
+            "blocks": self.convert_to_blocks(markdown),
+        })
+    }
+}
+
+/// Converts Markdown into mrkdwn using the default options.
+pub fn convert(markdown: &str) -> String {
+    Converter::new().convert(markdown)
+}
+
+/// Converts Slack mrkdwn back into CommonMark.
+pub fn to_commonmark(mrkdwn: &str) -> String {
+    let mut output = String::new();
+    commonmark::push_commonmark(&mut output, mrkdwn);
+    output
+}
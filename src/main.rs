@@ -1,17 +1,200 @@
 extern crate slackify_markdown;
 
-use slackify_markdown::slackdown;
+use slackify_markdown::batch::{self, InputFile};
+use slackify_markdown::html::HtmlPolicy;
+use slackify_markdown::mentions::{BroadcastPolicy, Directory};
+use slackify_markdown::server::ConvertServer;
+use slackify_markdown::watch::FileWatcher;
+use slackify_markdown::webhook::{PostOptions, Webhook};
+use slackify_markdown::{Converter, OutputFormat};
 
-use pulldown_cmark::{Options, Parser};
+use std::collections::HashSet;
+use std::fs;
 use std::io::{self, Read};
 use std::path::PathBuf;
+use std::sync::Arc;
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
 struct Cli {
-    #[structopt(short = "f", parse(from_os_str))]
-    /// Path to a file containing markdown. Input taken from stdin if omitted.
-    file: Option<PathBuf>,
+    #[structopt(short = "f", number_of_values = 1)]
+    /// Path to a file containing markdown, which can be given more than once.
+    /// Input taken from stdin if no files are given.
+    files: Vec<String>,
+
+    #[structopt(name = "INPUT")]
+    /// Files, directories or glob patterns to convert. Directories are searched
+    /// for .md and .markdown files.
+    inputs: Vec<String>,
+
+    #[structopt(long = "out-dir", parse(from_os_str))]
+    /// Write each converted file to this directory, mirroring the input tree,
+    /// instead of printing it.
+    out_dir: Option<PathBuf>,
+
+    #[structopt(long = "extension")]
+    /// Extension of the files written to --out-dir. Defaults to json for the
+    /// blocks and payload formats, md with --reverse, and txt otherwise.
+    extension: Option<String>,
+
+    #[structopt(long = "separator", default_value = "---")]
+    /// Line printed between converted files when several are printed.
+    separator: String,
+
+    #[structopt(short = "o", long = "output", parse(from_os_str))]
+    /// Write the output to this file instead of printing it.
+    output: Option<PathBuf>,
+
+    #[structopt(long = "watch")]
+    /// Keep running, converting the input files again each time they change.
+    watch: bool,
+
+    #[structopt(long = "clear")]
+    /// With --watch, clear the screen before printing fresh output.
+    clear: bool,
+
+    #[structopt(long = "format", default_value = "mrkdwn")]
+    /// Output format: mrkdwn, blocks (Block Kit JSON), payload (a chat.postMessage
+    /// or webhook JSON body) or plain (all formatting left out).
+    format: OutputFormat,
+
+    #[structopt(long = "reverse")]
+    /// Convert Slack mrkdwn back into Markdown instead.
+    reverse: bool,
+
+    #[structopt(long = "directory", parse(from_os_str))]
+    /// Path to a JSON or TOML file mapping user and channel handles to Slack IDs,
+    /// used to turn @user and #channel into mentions.
+    directory: Option<PathBuf>,
+
+    #[structopt(long = "allow-broadcast")]
+    /// Turn @here, @channel and @everyone into notifications. By default they
+    /// are neutralized so that they can't notify anyone.
+    allow_broadcast: bool,
+
+    #[structopt(long = "html", default_value = "convert")]
+    /// How HTML in the markdown is rendered: convert, strip, escape or passthrough.
+    html: HtmlPolicy,
+
+    #[structopt(long = "reflow")]
+    /// Join the lines of hard-wrapped paragraphs, so that Slack wraps them instead.
+    reflow: bool,
+
+    #[structopt(long = "divider")]
+    /// Line written for horizontal rules, such as ":wavy_dash::wavy_dash::wavy_dash:".
+    divider: Option<String>,
+
+    #[structopt(long = "code-labels")]
+    /// Label code blocks with their language.
+    code_labels: bool,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    #[structopt(name = "post")]
+    /// Post the converted markdown to a Slack incoming webhook.
+    Post(PostArgs),
+
+    #[structopt(name = "serve")]
+    /// Run an HTTP server that converts markdown POSTed to /convert, using the
+    /// conversion options given before the subcommand as defaults.
+    Serve(ServeArgs),
+}
+
+#[derive(StructOpt)]
+struct ServeArgs {
+    #[structopt(long = "addr", default_value = "127.0.0.1:8080")]
+    /// Address to listen on.
+    addr: String,
+
+    #[structopt(long = "max-body", default_value = "1048576")]
+    /// Largest request body accepted, in bytes.
+    max_body: usize,
+
+    #[structopt(long = "workers", default_value = "4")]
+    /// Number of requests handled at once.
+    workers: usize,
+}
+
+#[derive(StructOpt)]
+struct PostArgs {
+    #[structopt(long = "webhook-url", env = "SLACK_WEBHOOK_URL")]
+    /// URL of the incoming webhook to post to.
+    webhook_url: Option<String>,
+
+    #[structopt(long = "username")]
+    /// Name to post as, overriding the webhook's.
+    username: Option<String>,
+
+    #[structopt(long = "icon")]
+    /// Emoji, like :robot_face:, or image URL to post with, overriding the webhook's.
+    icon: Option<String>,
+
+    #[structopt(long = "channel")]
+    /// Channel to post to, overriding the webhook's.
+    channel: Option<String>,
+
+    #[structopt(long = "unfurl-links")]
+    /// Whether Slack shows previews of linked pages: true or false.
+    unfurl_links: Option<bool>,
+
+    #[structopt(long = "unfurl-media")]
+    /// Whether Slack shows previews of linked images and videos: true or false.
+    unfurl_media: Option<bool>,
+
+    #[structopt(long = "retries", default_value = "3")]
+    /// Number of times to retry while Slack is rate limiting the webhook.
+    retries: u32,
+
+    #[structopt(long = "dry-run")]
+    /// Print the payload instead of posting it.
+    dry_run: bool,
+}
+
+/// Posts the converted markdown to a webhook, or prints it for a dry run.
+fn post(args: &PostArgs, converter: &Converter, markdown: &str) -> io::Result<()> {
+    let mut payload = converter.convert_to_payload(markdown);
+    let options = PostOptions {
+        username: args.username.clone(),
+        icon: args.icon.clone(),
+        channel: args.channel.clone(),
+        unfurl_links: args.unfurl_links,
+        unfurl_media: args.unfurl_media,
+    };
+    options.apply(&mut payload);
+
+    if args.dry_run {
+        println!("{}", serde_json::to_string_pretty(&payload)?);
+        return Ok(());
+    }
+    let url = args.webhook_url.as_ref().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "no webhook URL, pass --webhook-url or set SLACK_WEBHOOK_URL",
+        )
+    })?;
+    Webhook::new(url).max_retries(args.retries).post(&payload)
+}
+
+/// Serves conversions until the process is interrupted or terminated, letting
+/// requests in progress finish.
+fn serve(args: &ServeArgs, converter: &Converter) -> io::Result<()> {
+    let server = ConvertServer::bind(&args.addr, converter.clone())?
+        .max_body(args.max_body)
+        .workers(args.workers);
+    let shutdown = server.shutdown_handle();
+    ctrlc::set_handler(move || shutdown.shutdown()).map_err(io::Error::other)?;
+
+    match server.local_addr() {
+        Some(addr) => eprintln!("listening on http://{}", addr),
+        None => eprintln!("listening on {}", args.addr),
+    }
+    server.run();
+    eprintln!("shut down");
+    Ok(())
 }
 
 fn get_sdtin() -> io::Result<String> {
@@ -20,31 +203,212 @@ fn get_sdtin() -> io::Result<String> {
     Ok(buffer)
 }
 
-fn slackify(markdown_input: String) -> String {
-    let mut options = Options::empty();
-    options.insert(Options::ENABLE_STRIKETHROUGH);
-    options.insert(Options::ENABLE_TASKLISTS);
-    let parser = Parser::new_ext(&markdown_input, options);
+/// Converts markdown, or posts it when running `post`, returning any output.
+fn process(args: &Cli, converter: &Converter, markdown: &str) -> io::Result<Option<String>> {
+    match &args.command {
+        Some(Command::Post(post_args)) => post(post_args, converter, markdown).map(|()| None),
+        _ if args.reverse => Ok(Some(slackify_markdown::to_commonmark(markdown))),
+        _ => Ok(Some(converter.convert(markdown))),
+    }
+}
+
+fn output_extension(args: &Cli) -> &str {
+    match (&args.extension, args.format) {
+        (Some(extension), _) => extension.trim_start_matches('.'),
+        _ if args.reverse => "md",
+        (None, OutputFormat::Blocks) | (None, OutputFormat::Payload) => "json",
+        (None, _) => "txt",
+    }
+}
+
+/// Converts each file named by the inputs, carrying on past any that fail,
+/// and returns whether they all succeeded.
+fn convert_files(args: &Cli, converter: &Converter, inputs: &[String]) -> bool {
+    let (files, mut failures) = batch::expand_inputs(inputs);
+    let mut converted = 0;
+    let mut printed = String::new();
+    let mut written = HashSet::new();
+    for file in &files {
+        let result = fs::read_to_string(&file.path)
+            .and_then(|markdown| process(args, converter, &markdown))
+            .and_then(|output| match output {
+                Some(output) => write_output(args, file, &output, &mut printed, &mut written),
+                None => Ok(()),
+            });
+        match result {
+            Ok(()) => converted += 1,
+            Err(e) => failures.push((file.path.display().to_string(), e)),
+        }
+    }
+    let mut printed_ok = true;
+    if !printed.is_empty() {
+        if let Err(e) = print_output(args, &printed) {
+            eprintln!("error writing output: {}", e);
+            printed_ok = false;
+        }
+    }
+
+    if failures.is_empty() {
+        if args.out_dir.is_some() {
+            eprintln!("converted {} files", converted);
+        }
+        return printed_ok;
+    }
+    for (input, e) in &failures {
+        eprintln!("error converting {}: {}", input, e);
+    }
+    eprintln!(
+        "converted {} of {} files, {} error{}",
+        converted,
+        files.len(),
+        failures.len(),
+        if failures.len() == 1 { "" } else { "s" }
+    );
+    false
+}
+
+/// Writes a converted file to the output directory, or adds it to the text
+/// to print. Refuses to overwrite a file in `written`, which it adds to.
+fn write_output(
+    args: &Cli,
+    file: &InputFile,
+    output: &str,
+    printed: &mut String,
+    written: &mut HashSet<PathBuf>,
+) -> io::Result<()> {
+    match &args.out_dir {
+        Some(out_dir) => {
+            let path = file.output_path(out_dir, output_extension(args));
+            if !written.insert(path.clone()) {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} was already written from another input", path.display()),
+                ));
+            }
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, format!("{}\n", output))
+        }
+        None => {
+            if !printed.is_empty() {
+                printed.push_str(&args.separator);
+                printed.push('\n');
+            }
+            printed.push_str(output);
+            printed.push('\n');
+            Ok(())
+        }
+    }
+}
+
+/// Prints the output, or writes it to the `--output` file.
+fn print_output(args: &Cli, output: &str) -> io::Result<()> {
+    match &args.output {
+        Some(path) => fs::write(path, output),
+        None => {
+            print!("{}", output);
+            Ok(())
+        }
+    }
+}
 
-    let mut output = String::new();
-    slackdown::push_slackdown(&mut output, parser);
-    output
+/// Converts the files named by the inputs each time they change, until the
+/// process is stopped.
+fn watch(args: &Cli, converter: &Converter, inputs: &[String]) -> io::Result<()> {
+    let mut watcher = FileWatcher::new(inputs)?;
+    if let Some(out_dir) = &args.out_dir {
+        watcher = watcher.ignore(out_dir);
+    }
+    if let Some(output) = &args.output {
+        watcher = watcher.ignore(output);
+    }
+    loop {
+        if args.clear {
+            // Clears the screen and moves the cursor to its top left.
+            print!("\x1b[2J\x1b[H");
+        }
+        convert_files(args, converter, inputs);
+        eprintln!("watching for changes, press Ctrl-C to stop");
+        watcher.wait()?;
+    }
 }
 
 fn main() {
     let args = Cli::from_args();
 
-    let input = match args.file {
-        Some(path) => std::fs::read_to_string(&path),
-        None => get_sdtin(),
+    let directory = match args
+        .directory
+        .as_ref()
+        .map(Directory::from_file)
+        .transpose()
+    {
+        Ok(directory) => directory.map(Arc::new),
+        Err(e) => {
+            eprintln!("error loading directory: {}", e);
+            std::process::exit(1);
+        }
     };
+    let mut converter = Converter::new()
+        .format(args.format)
+        .html(args.html)
+        .reflow(args.reflow)
+        .code_labels(args.code_labels);
+    if let Some(divider) = &args.divider {
+        converter = converter.divider(divider);
+    }
+    if args.allow_broadcast {
+        converter = converter.broadcast(BroadcastPolicy::Notify);
+    }
+    if let Some(directory) = &directory {
+        converter = converter.mentions(directory.clone());
+    }
 
-    match input {
-        Err(e) => println!("error receiving input: {:?}", e),
-        Ok(str) => {
-            let slacked = slackify(str);
-            println!("{}", slacked)
+    let inputs: Vec<String> = args.files.iter().chain(&args.inputs).cloned().collect();
+    let succeeded = if let Some(Command::Serve(serve_args)) = &args.command {
+        match serve(serve_args, &converter) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("error serving: {}", e);
+                false
+            }
+        }
+    } else if args.watch {
+        let result = match (&args.command, inputs.is_empty()) {
+            (Some(_), _) => Err("--watch can't be used with a subcommand".into()),
+            (None, true) => Err("--watch needs files to watch".into()),
+            (None, false) => watch(&args, &converter, &inputs).map_err(|e| e.to_string()),
+        };
+        if let Err(e) = result {
+            eprintln!("error: {}", e);
+        }
+        false
+    } else if inputs.is_empty() {
+        let result = get_sdtin()
+            .and_then(|markdown| process(&args, &converter, &markdown))
+            .and_then(|output| match output {
+                Some(output) => print_output(&args, &format!("{}\n", output)),
+                None => Ok(()),
+            });
+        match result {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                false
+            }
         }
+    } else {
+        convert_files(&args, &converter, &inputs)
+    };
+
+    if let Some(directory) = directory {
+        let unresolved = directory.take_unresolved();
+        if !unresolved.is_empty() {
+            eprintln!("unresolved mentions: {}", unresolved.join(", "));
+        }
+    }
+    if !succeeded {
+        std::process::exit(1);
     }
 }
 
@@ -52,12 +416,17 @@ fn main() {
 mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
     use super::*;
+    use slackify_markdown::snippet::Snippet;
+
+    fn slackify(markdown_input: String) -> String {
+        slackify_markdown::convert(&markdown_input)
+    }
 
     #[test]
     fn test_header_to_bold() {
         let input = "## This is a title".to_string();
         let actual = slackify(input);
-        let expected = "*This is a title*\n";
+        let expected = "*This is a title*";
         assert_eq!(actual, expected);
     }
 
@@ -90,7 +459,7 @@ mod tests {
         let input = "This string includes a [https://hyperlink.com.au](https://hyperlink.com.au)"
             .to_string();
         let actual = slackify(input);
-        let expected = "This string includes a https://hyperlink.com.au".to_string();
+        let expected = "This string includes a <https://hyperlink.com.au>".to_string();
         assert_eq!(actual, expected);
     }
 
@@ -100,10 +469,137 @@ mod tests {
             "The 44th President was [Barack Obama](https://en.wikipedia.org/wiki/Barack_Obama)."
                 .to_string();
         let actual = slackify(input);
-        let expected = "The 44th President was Barack Obama.";
+        let expected =
+            "The 44th President was <https://en.wikipedia.org/wiki/Barack_Obama|Barack Obama>.";
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_hyperlink_formatting_and_title() {
+        let input = "See [the **new** docs](https://example.com/a|b \"Docs\") and <me@example.com>"
+            .to_string();
+        let actual = slackify(input);
+        let expected =
+            "See <https://example.com/a%7Cb|the *new* docs> and <mailto:me@example.com|me@example.com>";
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_reference_hyperlinks() {
+        let input = "Read the [release notes][notes] or [notes].
+
+[notes]: https://example.com/notes?a=1&b=2"
+            .to_string();
+        let actual = slackify(input);
+        let expected = "Read the <https://example.com/notes?a=1&amp;b=2|release notes> or \
+                        <https://example.com/notes?a=1&amp;b=2|notes>.";
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_relative_links_written_as_text() {
+        let input =
+            "See [install](#installation), [u](@U123), [empty]() and [docs](docs/setup.md)."
+                .to_string();
+        let actual = slackify(input);
+        let expected = "See install, u, empty and docs.";
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_slack_control_characters_escaped() {
+        let input = "Returns a Vec<String> if a && b, see `Option<T>`".to_string();
+        let actual = slackify(input);
+        let expected = "Returns a Vec&lt;String&gt; if a &amp;&amp; b, see `Option&lt;T&gt;`";
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_code_block_control_characters_escaped() {
+        let input = "```\nif a < b && b > c {}\n```".to_string();
+        let actual = slackify(input);
+        let expected = "```if a &lt; b &amp;&amp; b &gt; c {}\n```";
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_table_alignment() {
+        let input = "| Fruit | Qty | Note |
+|:------|----:|:----:|
+| 🍎 apple | 3 | *fresh* |
+| 梨 | 12 | ok |"
+            .to_string();
+        let actual = slackify(input);
+        let expected = "```
+Fruit    | Qty | Note
+---------+-----+------
+🍎 apple |   3 | fresh
+梨       |  12 |  ok
+```";
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_images() {
+        let input = "Logo: ![The *logo*](https://example.com/logo.png \"Logo\")".to_string();
+        let actual = slackify(input);
+        let expected = "Logo: <https://example.com/logo.png|The logo>";
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_linked_images() {
+        let input = "[![build](https://x.io/badge.png)](https://x.io), \
+                     <a href=\"https://x.io\"><img src=\"https://x.io/b.png\" alt=\"ci\"></a> \
+                     and ![logo](docs/logo.png)"
+            .to_string();
+        let actual = slackify(input);
+        let expected = "<https://x.io|build>, <https://x.io|ci> and logo";
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_footnotes_collected_at_end() {
+        let input = "[^late]: Defined before use.
+
+Claim one[^1] and claim two[^late].
+
+[^1]: The source.
+
+More text."
+            .to_string();
+        let actual = slackify(input);
+        let expected = "Claim one[1] and claim two[2].
+
+More text.
+
+*Notes*
+[1] The source.
+[2] Defined before use.";
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_task_lists() {
+        let input = "- [x] Ship the release
+- [ ] Write the announcement
+    - [x] Draft
+    - Review
+1. [ ] Numbered task"
+            .to_string();
+        let actual = slackify(input);
+        let expected = "☑ Ship the release
+☐ Write the announcement
+    ☑ Draft
+    • Review
+
+1. ☐ Numbered task";
+        assert_eq!(actual, expected);
+
+        let input = "- [x] done\n\n- [ ] loose todo".to_string();
+        assert_eq!(slackify(input), "☑ done\n\n☐ loose todo");
+    }
+
     #[test]
     fn test_quote_formatting() {
         let input = "The following is a quote:
@@ -111,9 +607,36 @@ mod tests {
 The end."
             .to_string();
         let expected = "The following is a quote:
+
 > Education is a system of imposed ignorance. - N. Chomsky
-The end.
-";
+> The end.";
+        let actual = slackify(input);
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_quote_every_line_prefixed() {
+        let input = "> Steps:
+> 1. Build
+> 2. Ship
+>
+> ```
+> cargo build
+> ```
+>
+> > Nested quotes are flattened
+> > into the outer one"
+            .to_string();
+        let expected = "> Steps:
+>
+> 1. Build
+> 2. Ship
+>
+> ```cargo build
+> ```
+>
+> Nested quotes are flattened
+> into the outer one";
         let actual = slackify(input);
         assert_eq!(actual, expected);
     }
@@ -124,7 +647,9 @@ The end.
         let input =
             "- Friday was a bit disrupted by \"Permissions pain 😭\", so today I will still be ..."
                 .to_string();
-        let expected = "• Friday was a bit disrupted by \"Permissions pain 😭\", so today I will still be ...\n".to_string();
+        let expected =
+            "• Friday was a bit disrupted by \"Permissions pain 😭\", so today I will still be ..."
+                .to_string();
         let actual = slackify(input);
 
         assert_eq!(actual, expected);
@@ -136,13 +661,403 @@ The end.
 2. This is the 2nd item"
             .to_string();
         let expected = "1. This is the first item
-2. This is the 2nd item\n"
+2. This is the 2nd item"
+            .to_string();
+        let actual = slackify(input);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_ordered_list_start_number() {
+        let input = "3. Third
+4. Fourth"
+            .to_string();
+        let expected = "3. Third
+4. Fourth"
+            .to_string();
+        let actual = slackify(input);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_mixed_nested_lists() {
+        let input = "1. First
+    - Bullet
+        5. Five
+        6. Six
+    - Another bullet
+2. Second"
+            .to_string();
+        let expected = "1. First
+    • Bullet
+        5. Five
+        6. Six
+    • Another bullet
+2. Second"
+            .to_string();
+        let actual = slackify(input);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_list_item_blocks_indented() {
+        let input = "- top
+    - nested item
+
+      continuation paragraph"
+            .to_string();
+        let expected = "• top
+    • nested item
+
+      continuation paragraph";
+        assert_eq!(slackify(input), expected);
+
+        let input = "1. step
+
+   ```sh
+   make
+   ```
+2. next
+
+   > quoted"
+            .to_string();
+        let expected = "1. step
+
+   ```make
+   ```
+
+2. next
+
+   > quoted";
+        assert_eq!(slackify(input), expected);
+
+        let input = "> - item
+>
+>   second para
+>
+> 1. step
+>
+>    ```sh
+>    make
+>    ```"
+            .to_string();
+        let expected = "> • item
+>
+>   second para
+>
+> 1. step
+>
+>    ```make
+>    ```";
+        assert_eq!(slackify(input), expected);
+    }
+
+    #[test]
+    fn test_render_style() {
+        use slackify_markdown::style::{HeadingStyle, Indent, Numbering, RenderStyle};
+        use slackify_markdown::Converter;
+
+        let style = RenderStyle {
+            headings: vec![
+                HeadingStyle::new(":mega: *{}*").uppercase(),
+                HeadingStyle::new("*{}*"),
+                HeadingStyle::new("*_{}_*"),
+            ],
+            bullets: vec!["•".to_string(), "◦".to_string(), "▪".to_string()],
+            numbering: vec![
+                Numbering::Decimal,
+                Numbering::LowerAlpha,
+                Numbering::LowerRoman,
+            ],
+            indent: Indent::Spaces(2),
+        };
+        let input = "# Big news
+### Small print
+1. One
+    1. Sub one
+        - Point
+            1. Deep";
+        let actual = Converter::new().style(style).convert(input);
+        let expected = ":mega: *BIG NEWS*
+
+*_Small print_*
+
+1. One
+  a. Sub one
+    ▪ Point
+      1. Deep";
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_mentions() {
+        let directory = Directory::from_json(
+            r#"{ "users": { "alice": "U123" }, "channels": { "deploys": "C456" } }"#,
+        )
+        .unwrap();
+        let directory = Arc::new(directory);
+        let input = "Ping @alice and @bob in #deploys, not `@alice` or [@alice](https://x.io).";
+        let actual = Converter::new().mentions(directory.clone()).convert(input);
+        let expected =
+            "Ping <@U123> and @bob in <#C456|deploys>, not `@alice` or <https://x.io|@alice>.";
+        assert_eq!(actual, expected);
+        assert_eq!(directory.take_unresolved(), vec!["@bob"]);
+
+        let input = "```\n@alice in #deploys, cc @here\n```";
+        let actual = Converter::new().mentions(directory.clone()).convert(input);
+        assert_eq!(actual, "```@alice in #deploys, cc @here\n```");
+        assert!(directory.take_unresolved().is_empty());
+    }
+
+    #[test]
+    fn test_broadcasts_neutralized_by_default() {
+        let input = "Hey @here and @Channel, see <!everyone> and <!subteam^S1>".to_string();
+        let actual = slackify(input);
+        let expected =
+            "Hey @\u{2060}here and @\u{2060}Channel, see &lt;!everyone&gt; and &lt;!subteam^S1&gt;";
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_broadcast_destinations_refused() {
+        let input = "[hi](!channel), <a href=\"!everyone\">click</a>, ![x](!here), \
+                     [u](@U123) and [c](#C456)";
+        let expected = "hi, click, x, u and c";
+        for broadcast in [BroadcastPolicy::Neutralize, BroadcastPolicy::Notify] {
+            let actual = Converter::new()
+                .html(HtmlPolicy::Convert)
+                .broadcast(broadcast)
+                .convert(input);
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_plain_broadcasts_neutralized() {
+        let input = "Hey @here, see <!channel> and `<!everyone>`";
+        let plain = Converter::new().format(OutputFormat::Plain);
+        assert_eq!(
+            plain.convert(input),
+            "Hey @\u{2060}here, see <\u{2060}!channel> and <\u{2060}!everyone>"
+        );
+        assert_eq!(
+            plain.broadcast(BroadcastPolicy::Notify).convert(input),
+            "Hey <!here>, see <!channel> and <!everyone>"
+        );
+    }
+
+    #[test]
+    fn test_broadcasts_allowed() {
+        let input = "Hey @here and @everyone, not [@channel](https://x.io)";
+        let actual = Converter::new()
+            .broadcast(BroadcastPolicy::Notify)
+            .convert(input);
+        let expected = "Hey <!here> and <!everyone>, not <https://x.io|@\u{2060}channel>";
+        assert_eq!(actual, expected);
+
+        let input =
+            "![@here look](https://x.io/a.png) <img src=\"https://x.io/b.png\" alt=\"@channel\">";
+        let actual = Converter::new()
+            .broadcast(BroadcastPolicy::Notify)
+            .convert(input);
+        let expected =
+            "<https://x.io/a.png|@\u{2060}here look> <https://x.io/b.png|@\u{2060}channel>";
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_inline_html_converted() {
+        let input = "Some <b>bold</b>, <em>emphasis</em> and <code>a &lt; b</code>,<br>
+see <a href=\"https://x.io/?a=1&amp;b=2\">the <strong>docs</strong></a> <!-- todo -->for Vec<String>"
             .to_string();
         let actual = slackify(input);
+        let expected = "Some *bold*, _emphasis_ and `a &lt; b`,
+see <https://x.io/?a=1&amp;b=2|the *docs*> for Vec&lt;String&gt;";
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_html_block_converted() {
+        let input = "<details>
+<summary>Release notes</summary>
+
+Fixed the build.
+
+</details>
+
+<p align=\"center\">
+  <img src=\"https://x.io/logo.png\" alt=\"Logo\">
+  Our   project
+</p>";
+        let actual = Converter::new().convert(input);
+        let expected = "*Release notes*
+
+Fixed the build.
+
+<https://x.io/logo.png|Logo> Our project";
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_html_policies() {
+        let input = "Some <b>bold</b> <x-tag>text</x-tag> <!here>";
+        let convert = |policy| Converter::new().html(policy).convert(input);
+        assert_eq!(convert(HtmlPolicy::Strip), "Some bold text &lt;!here&gt;");
+        assert_eq!(
+            convert(HtmlPolicy::Escape),
+            "Some &lt;b&gt;bold&lt;/b&gt; &lt;x-tag&gt;text&lt;/x-tag&gt; &lt;!here&gt;"
+        );
+        assert_eq!(
+            convert(HtmlPolicy::Passthrough),
+            "Some <b>bold</b> <x-tag>text</x-tag> &lt;!here&gt;"
+        );
+    }
+
+    #[test]
+    fn test_unbalanced_html() {
+        let input = "Text </b> stray </em> closers, <i>open <b>and</b> shut</i>";
+        let expected = "Text  stray  closers, _open *and* shut_";
+        assert_eq!(Converter::new().convert(input), expected);
+
+        let input = "<b>bold <i>and italic\npara\n\nNext";
+        let expected = "*bold _and italic\npara_*\n\nNext";
+        assert_eq!(Converter::new().convert(input), expected);
+
+        let input = "> - a\n>   > nested <a href=\"https://x.io\">open\n\nAfter";
+        let expected = "> • a\n>   nested open\n\nAfter";
+        assert_eq!(Converter::new().convert(input), expected);
+    }
+
+    #[test]
+    fn test_reflow() {
+        let input = "A paragraph that was
+hard-wrapped at 80 columns,  
+with a hard break.
+
+> Quoted and
+> wrapped";
+        let expected = "A paragraph that was
+hard-wrapped at 80 columns,
+with a hard break.
+
+> Quoted and
+> wrapped";
+        assert_eq!(Converter::new().convert(input), expected);
+
+        let expected = "A paragraph that was hard-wrapped at 80 columns,
+with a hard break.
 
+> Quoted and wrapped";
+        assert_eq!(Converter::new().reflow(true).convert(input), expected);
+    }
+
+    #[test]
+    fn test_block_spacing() {
+        let input = "
+
+# Release
+First paragraph.
+
+Second paragraph.
+- tight
+-
+- list
+
+1. loose
+
+2. list
+```
+code
+```
+Last.
+
+";
+        let expected = "*Release*
+
+First paragraph.
+
+Second paragraph.
+
+• tight
+•\u{20}
+• list
+
+1. loose
+
+2. list
+
+```code
+```
+
+Last.";
+        assert_eq!(Converter::new().convert(input), expected);
+    }
+
+    #[test]
+    fn test_rules_become_dividers() {
+        let input = "Part one\n\n---\nPart two\n\n<hr>";
+        let expected = "Part one\n\n────────────────────\n\nPart two\n\n────────────────────";
+        assert_eq!(Converter::new().convert(input), expected);
+
+        let actual = Converter::new().divider(":wavy_dash:").convert(input);
+        assert_eq!(actual, "Part one\n\n:wavy_dash:\n\nPart two\n\n:wavy_dash:");
+    }
+
+    #[test]
+    fn test_code_labels() {
+        let input = "```rust\nlet a = b && c;\n```\n\n```\nplain\n```";
+        let actual = Converter::new().code_labels(true).convert(input);
+        let expected = "_rust_\n```let a = b &amp;&amp; c;\n```\n\n```plain\n```";
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_snippets_extracted() {
+        let input = "Short:\n\n```sh\nmake\n```\n\nLong:\n\n```rust:src/main.rs\nfn main() {\n    a < b;\n}\n```";
+        let (text, snippets) = Converter::new()
+            .snippet_threshold(2)
+            .convert_with_snippets(input);
+        assert_eq!(
+            text,
+            "Short:\n\n```make\n```\n\nLong:\n\n_Snippet 1: src/main.rs_"
+        );
+        assert_eq!(
+            snippets,
+            vec![Snippet {
+                language: Some("rust".to_string()),
+                filename: Some("src/main.rs".to_string()),
+                content: "fn main() {\n    a < b;\n}\n".to_string(),
+            }]
+        );
+
+        // Without anywhere to put snippets, the code stays in the message.
+        let converter = Converter::new().snippet_threshold(2);
+        assert!(converter.convert(input).contains("a &lt; b;"));
+        let payload = converter.format(OutputFormat::Payload).convert(input);
+        assert!(!payload.contains("Snippet 1"));
+    }
+
+    #[test]
+    fn test_output_formats() {
+        let input = "# News\n\nSee **[docs](https://x.io)** & `code`";
+        let converter = Converter::new();
+
+        let plain = converter.clone().format(OutputFormat::Plain).convert(input);
+        assert_eq!(plain, "News\n\nSee docs (https://x.io) & code");
+
+        let payload = converter.format(OutputFormat::Payload).convert(input);
+        let payload: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        assert_eq!(
+            payload["text"],
+            "*News*\n\nSee *<https://x.io|docs>* &amp; `code`"
+        );
+        assert_eq!(payload["blocks"][0]["type"], "header");
+        assert_eq!(payload["blocks"].as_array().unwrap().len(), 2);
+    }
+
     #[test]
     fn test_list_subitem_formatting() {
         let input = "- ⌗ redacted redacted redacted redacted redacted
@@ -165,10 +1080,21 @@ The end.
 • Cleaning up in AWS
     • Sub-item 1
     • Sub-item 2
-
 • Got heads-up from redacted redacted redacted redacted redacted redacted
-    • redacted redacted redacted redacted redacted redacted redacted errors.\n\n"
+    • redacted redacted redacted redacted redacted redacted redacted errors."
             .to_string();
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn test_output_extension() {
+        let extension = |args: &[&str]| {
+            let args = Cli::from_iter(["slackify-markdown"].iter().chain(args));
+            output_extension(&args).to_string()
+        };
+        assert_eq!(extension(&["notes/"]), "txt");
+        assert_eq!(extension(&["--format", "payload", "notes/"]), "json");
+        assert_eq!(extension(&["--reverse", "notes/"]), "md");
+        assert_eq!(extension(&["--extension", ".slack", "notes/"]), "slack");
+    }
 }
@@ -0,0 +1,213 @@
+//! Resolution of `@user` and `#channel` handles into Slack mentions, which
+//! notify the user or link the channel.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+use std::sync::Mutex;
+
+use serde::Deserialize;
+
+/// Maps handles written in Markdown, without their `@` or `#`, to Slack IDs.
+pub trait MentionResolver: fmt::Debug + Send + Sync {
+    /// Returns the ID of a user, such as `U024BE7LH`.
+    fn resolve_user(&self, handle: &str) -> Option<String>;
+
+    /// Returns the ID of a channel, such as `C024BE91L`.
+    fn resolve_channel(&self, name: &str) -> Option<String>;
+}
+
+/// A `MentionResolver` backed by a directory of users and channels, which
+/// records the handles it couldn't resolve.
+///
+/// Directories are loaded from JSON or TOML files of the form:
+///
+/// ```toml
+/// [users]
+/// alice = "U024BE7LH"
+///
+/// [channels]
+/// deploys = "C024BE91L"
+/// ```
+#[derive(Debug, Default)]
+pub struct Directory {
+    users: HashMap<String, String>,
+    channels: HashMap<String, String>,
+    unresolved: Mutex<BTreeSet<String>>,
+}
+
+#[derive(Deserialize)]
+struct DirectoryFile {
+    #[serde(default)]
+    users: HashMap<String, String>,
+    #[serde(default)]
+    channels: HashMap<String, String>,
+}
+
+impl Directory {
+    pub fn new<U, C>(users: U, channels: C) -> Self
+    where
+        U: IntoIterator<Item = (String, String)>,
+        C: IntoIterator<Item = (String, String)>,
+    {
+        Self {
+            users: lowercase_keys(users),
+            channels: lowercase_keys(channels),
+            unresolved: Mutex::default(),
+        }
+    }
+
+    /// Loads a directory from a `.json` or `.toml` file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Self::from_toml(&contents),
+            _ => Self::from_json(&contents),
+        }
+    }
+
+    pub fn from_json(contents: &str) -> io::Result<Self> {
+        let file: DirectoryFile = serde_json::from_str(contents)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        Ok(Self::new(file.users, file.channels))
+    }
+
+    pub fn from_toml(contents: &str) -> io::Result<Self> {
+        let file: DirectoryFile =
+            toml::from_str(contents).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        Ok(Self::new(file.users, file.channels))
+    }
+
+    /// Returns the handles that couldn't be resolved so far, such as `@bob`
+    /// and `#random`, and forgets them.
+    pub fn take_unresolved(&self) -> Vec<String> {
+        let mut unresolved = self.unresolved.lock().unwrap();
+        std::mem::take(&mut *unresolved).into_iter().collect()
+    }
+
+    fn lookup(&self, map: &HashMap<String, String>, sigil: char, handle: &str) -> Option<String> {
+        let id = map.get(&handle.to_lowercase()).cloned();
+        if id.is_none() {
+            let mut unresolved = self.unresolved.lock().unwrap();
+            unresolved.insert(format!("{}{}", sigil, handle));
+        }
+        id
+    }
+}
+
+impl MentionResolver for Directory {
+    fn resolve_user(&self, handle: &str) -> Option<String> {
+        self.lookup(&self.users, '@', handle)
+    }
+
+    fn resolve_channel(&self, name: &str) -> Option<String> {
+        self.lookup(&self.channels, '#', name)
+    }
+}
+
+fn lowercase_keys<I>(entries: I) -> HashMap<String, String>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    entries
+        .into_iter()
+        .map(|(k, v)| (k.trim_start_matches(['@', '#']).to_lowercase(), v))
+        .collect()
+}
+
+/// What to do with `@here`, `@channel` and `@everyone`, which notify everyone
+/// in a channel.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BroadcastPolicy {
+    /// Leave them as text that can't notify anyone. Any `<!here>`-style
+    /// sequences already in the text are escaped regardless.
+    #[default]
+    Neutralize,
+    /// Turn them into `<!here>`, `<!channel>` and `<!everyone>`, which notify.
+    Notify,
+}
+
+const BROADCASTS: &[&str] = &["here", "channel", "everyone"];
+
+pub(crate) fn is_broadcast(handle: &str) -> bool {
+    BROADCASTS.iter().any(|b| b.eq_ignore_ascii_case(handle))
+}
+
+/// A handle found in text.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Mention<'t> {
+    User(&'t str),
+    Channel(&'t str),
+}
+
+/// Finds the mentions in text, along with their byte ranges. A mention must
+/// start a word, so neither `me@example.com` nor `C#` contain one.
+pub(crate) fn find_mentions(text: &str) -> Vec<(usize, usize, Mention<'_>)> {
+    let mut found = Vec::new();
+    let mut prev: Option<char> = None;
+    let mut iter = text.char_indices().peekable();
+    while let Some((i, c)) = iter.next() {
+        let starts_word = prev.is_none_or(|p| !p.is_alphanumeric() && p != '@' && p != '#');
+        prev = Some(c);
+        if (c != '@' && c != '#') || !starts_word {
+            continue;
+        }
+        let rest = &text[i + 1..];
+        let len = rest
+            .find(|ch: char| !(ch.is_alphanumeric() || ch == '.' || ch == '_' || ch == '-'))
+            .unwrap_or(rest.len());
+        // Trailing punctuation ends a sentence rather than being part of the handle.
+        let handle = rest[..len].trim_end_matches(['.', '-', '_']);
+        if handle.is_empty() {
+            continue;
+        }
+        // `#123` refers to an issue rather than a channel.
+        if c == '#' && handle.chars().all(|ch| ch.is_ascii_digit()) {
+            continue;
+        }
+        let end = i + 1 + handle.len();
+        let mention = if c == '@' {
+            Mention::User(handle)
+        } else {
+            Mention::Channel(handle)
+        };
+        found.push((i, end, mention));
+        while iter.peek().is_some_and(|&(j, _)| j < end) {
+            prev = iter.next().map(|(_, ch)| ch);
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_mentions() {
+        let found =
+            find_mentions("Thanks @alice.b and #deploys-prod. Mail me@example.com re #42 in C#.");
+        assert_eq!(
+            found,
+            vec![
+                (7, 15, Mention::User("alice.b")),
+                (20, 33, Mention::Channel("deploys-prod")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_directory_from_toml() {
+        let directory =
+            Directory::from_toml("[users]\nAlice = \"U1\"\n\n[channels]\n\"#deploys\" = \"C1\"\n")
+                .unwrap();
+        assert_eq!(directory.resolve_user("alice"), Some("U1".to_string()));
+        assert_eq!(directory.resolve_channel("deploys"), Some("C1".to_string()));
+        assert_eq!(directory.resolve_user("bob"), None);
+        assert_eq!(directory.take_unresolved(), vec!["@bob"]);
+        assert!(directory.take_unresolved().is_empty());
+    }
+}
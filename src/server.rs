@@ -0,0 +1,331 @@
+//! A small HTTP server that converts Markdown, for services that can't use the
+//! library directly.
+//!
+//! * `POST /convert` converts the Markdown in the request body. Options are
+//!   given in the query string, like `/convert?format=blocks&reflow=true`, or,
+//!   when the body is JSON, alongside the Markdown:
+//!   `{"markdown": "# Title", "format": "blocks"}`.
+//! * `GET /health` responds with `ok`.
+
+use std::io::{self, Read};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use serde::Deserialize;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::html::HtmlPolicy;
+use crate::{Converter, OutputFormat};
+
+/// The largest request body accepted, in bytes.
+pub const DEFAULT_MAX_BODY: usize = 1024 * 1024;
+
+/// Number of requests handled at once.
+pub const DEFAULT_WORKERS: usize = 4;
+
+/// Serves conversions with a `Converter`, whose options each request can
+/// override.
+pub struct ConvertServer {
+    server: Arc<Server>,
+    converter: Converter,
+    max_body: usize,
+    workers: usize,
+    stopping: Arc<AtomicBool>,
+}
+
+/// Stops a running `ConvertServer`, from another thread or a signal handler.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    server: Arc<Server>,
+    stopping: Arc<AtomicBool>,
+}
+
+impl ShutdownHandle {
+    /// Stops accepting requests. `ConvertServer::run` returns once the
+    /// requests being handled have been responded to.
+    pub fn shutdown(&self) {
+        if !self.stopping.swap(true, Ordering::SeqCst) {
+            self.server.unblock();
+        }
+    }
+}
+
+impl ConvertServer {
+    /// Listens on `addr`, such as `127.0.0.1:8080`.
+    pub fn bind<A: ToSocketAddrs>(addr: A, converter: Converter) -> io::Result<Self> {
+        Ok(Self {
+            server: Arc::new(Server::http(addr).map_err(io::Error::other)?),
+            converter,
+            max_body: DEFAULT_MAX_BODY,
+            workers: DEFAULT_WORKERS,
+            stopping: Arc::default(),
+        })
+    }
+
+    pub fn max_body(mut self, bytes: usize) -> Self {
+        self.max_body = bytes;
+        self
+    }
+
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle {
+            server: self.server.clone(),
+            stopping: self.stopping.clone(),
+        }
+    }
+
+    /// Handles requests until the server is shut down.
+    pub fn run(&self) {
+        thread::scope(|scope| {
+            for _ in 0..self.workers {
+                scope.spawn(|| self.work());
+            }
+        });
+    }
+
+    fn work(&self) {
+        while !self.stopping.load(Ordering::SeqCst) {
+            let mut request = match self.server.recv() {
+                Ok(request) => request,
+                Err(_) => continue,
+            };
+            let reply = self.handle(&mut request);
+            // Nothing can be done if the client has gone away.
+            let _ = request.respond(reply.into_response());
+        }
+        // Only one worker is woken by each unblock, so pass it on to the next.
+        self.server.unblock();
+    }
+
+    fn handle(&self, request: &mut Request) -> Reply {
+        let url = request.url().to_string();
+        let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+        match (request.method(), path) {
+            (Method::Get, "/health") => Reply::text(200, "ok"),
+            (Method::Post, "/convert") => self.convert(request, query).unwrap_or_else(|e| e),
+            (_, "/health") => Reply::method_not_allowed("GET"),
+            (_, "/convert") => Reply::method_not_allowed("POST"),
+            _ => Reply::text(404, "not found"),
+        }
+    }
+
+    fn convert(&self, request: &mut Request, query: &str) -> Result<Reply, Reply> {
+        let too_large = || Reply::text(413, format!("body is over {} bytes", self.max_body));
+        if request.body_length().is_some_and(|len| len > self.max_body) {
+            return Err(too_large());
+        }
+        let mut body = Vec::new();
+        request
+            .as_reader()
+            .take(self.max_body as u64 + 1)
+            .read_to_end(&mut body)
+            .map_err(|e| Reply::text(400, e.to_string()))?;
+        if body.len() > self.max_body {
+            return Err(too_large());
+        }
+        let body =
+            String::from_utf8(body).map_err(|_| Reply::text(400, "body isn't valid UTF-8"))?;
+
+        let is_json = request.headers().iter().any(|header| {
+            header.field.equiv("Content-Type")
+                && header.value.as_str().starts_with("application/json")
+        });
+        let (options, markdown) = if is_json {
+            let mut options: ConvertOptions = serde_json::from_str(&body)
+                .map_err(|e| Reply::text(400, format!("invalid JSON body: {}", e)))?;
+            let markdown = options
+                .markdown
+                .take()
+                .ok_or_else(|| Reply::text(400, "JSON body has no \"markdown\""))?;
+            (options, markdown)
+        } else {
+            (ConvertOptions::from_query(query)?, body)
+        };
+
+        let converter = options.apply(self.converter.clone())?;
+        let output = converter.convert(&markdown);
+        Ok(match converter.options().format {
+            OutputFormat::Blocks | OutputFormat::Payload => Reply::json(output),
+            OutputFormat::Mrkdwn | OutputFormat::Plain => Reply::text(200, output),
+        })
+    }
+}
+
+/// Options that a request can set, named as on the command line.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConvertOptions {
+    markdown: Option<String>,
+    format: Option<String>,
+    html: Option<String>,
+    reflow: Option<bool>,
+    code_labels: Option<bool>,
+    divider: Option<String>,
+}
+
+impl ConvertOptions {
+    fn from_query(query: &str) -> Result<Self, Reply> {
+        let mut options = ConvertOptions::default();
+        for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+            let value = value.into_owned();
+            match key.as_ref() {
+                "format" => options.format = Some(value),
+                "html" => options.html = Some(value),
+                "divider" => options.divider = Some(value),
+                "reflow" => options.reflow = Some(parse_flag(&key, &value)?),
+                "code_labels" => options.code_labels = Some(parse_flag(&key, &value)?),
+                _ => return Err(Reply::text(400, format!("unknown option '{}'", key))),
+            }
+        }
+        Ok(options)
+    }
+
+    fn apply(&self, mut converter: Converter) -> Result<Converter, Reply> {
+        if let Some(format) = &self.format {
+            let format: OutputFormat = format.parse().map_err(|e| Reply::text(400, e))?;
+            converter = converter.format(format);
+        }
+        if let Some(html) = &self.html {
+            let html: HtmlPolicy = html.parse().map_err(|e| Reply::text(400, e))?;
+            converter = converter.html(html);
+        }
+        if let Some(reflow) = self.reflow {
+            converter = converter.reflow(reflow);
+        }
+        if let Some(code_labels) = self.code_labels {
+            converter = converter.code_labels(code_labels);
+        }
+        if let Some(divider) = &self.divider {
+            converter = converter.divider(divider);
+        }
+        Ok(converter)
+    }
+}
+
+/// Parses a flag from the query string, where `?reflow` alone means true.
+fn parse_flag(key: &str, value: &str) -> Result<bool, Reply> {
+    match value {
+        "" | "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        _ => Err(Reply::text(
+            400,
+            format!(
+                "invalid value '{}' for {}, expected true or false",
+                value, key
+            ),
+        )),
+    }
+}
+
+struct Reply {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+    allow: Option<&'static str>,
+}
+
+impl Reply {
+    fn text<S: Into<String>>(status: u16, body: S) -> Self {
+        Reply {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.into(),
+            allow: None,
+        }
+    }
+
+    fn json(body: String) -> Self {
+        Reply {
+            content_type: "application/json",
+            ..Reply::text(200, body)
+        }
+    }
+
+    fn method_not_allowed(allow: &'static str) -> Self {
+        Reply {
+            allow: Some(allow),
+            ..Reply::text(405, "method not allowed")
+        }
+    }
+
+    fn into_response(self) -> Response<io::Cursor<Vec<u8>>> {
+        let header = |field: &str, value: &str| Header::from_bytes(field, value).unwrap();
+        let mut response = Response::from_string(self.body)
+            .with_status_code(self.status)
+            .with_header(header("Content-Type", self.content_type));
+        if let Some(allow) = self.allow {
+            response.add_header(header("Allow", allow));
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn status(result: Result<ureq::Response, ureq::Error>) -> (u16, String) {
+        match result {
+            Ok(response) => (response.status(), response.into_string().unwrap()),
+            Err(ureq::Error::Status(status, response)) => (status, response.into_string().unwrap()),
+            Err(e) => panic!("request failed: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_convert_server() {
+        let server = ConvertServer::bind("127.0.0.1:0", Converter::new())
+            .unwrap()
+            .max_body(64)
+            .workers(2);
+        let url = format!("http://{}", server.local_addr().unwrap());
+        let shutdown = server.shutdown_handle();
+        let handle = thread::spawn(move || server.run());
+
+        let (code, body) = status(ureq::get(&format!("{}/health", url)).call());
+        assert_eq!((code, body.as_str()), (200, "ok"));
+
+        let convert = format!("{}/convert", url);
+        let (code, body) = status(ureq::post(&convert).send_string("Hi **there**"));
+        assert_eq!((code, body.as_str()), (200, "Hi *there*"));
+
+        let response = ureq::post(&format!("{}?format=blocks", convert))
+            .send_string("# Hi")
+            .unwrap();
+        assert_eq!(response.content_type(), "application/json");
+        let blocks: Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(blocks[0]["type"], "header");
+
+        let (code, body) = status(
+            ureq::post(&convert)
+                .set("Content-Type", "application/json")
+                .send_string(r#"{"markdown": "a\nb", "reflow": true}"#),
+        );
+        assert_eq!((code, body.as_str()), (200, "a b"));
+
+        let (code, body) = status(ureq::post(&format!("{}?format=html", convert)).send_string("x"));
+        assert_eq!(code, 400);
+        assert!(body.starts_with("unknown format 'html'"));
+
+        let (code, _) = status(ureq::post(&convert).send_string(&"x".repeat(65)));
+        assert_eq!(code, 413);
+
+        let (code, _) = status(ureq::get(&convert).call());
+        assert_eq!(code, 405);
+
+        shutdown.shutdown();
+        handle.join().unwrap();
+    }
+}
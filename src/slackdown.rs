@@ -41,31 +41,195 @@
 //! Slack-flavored markdown (Slackdown) renderer that takes an iterator of events as input.
 
 use std::collections::HashMap;
-use std::fmt::{Arguments, Write as FmtWrite};
-use std::io::{self, ErrorKind, Write};
+use std::io::{self, Write};
+use std::iter::Peekable;
+use std::sync::Arc;
 
-use crate::escape::escape_href;
+use crate::escape::{escape_slack, escape_slack_href};
+use crate::html::{
+    decode_entities, is_block_tag, is_known_tag, text_content, tokenize, HtmlPolicy, Token,
+};
+use crate::mentions::{find_mentions, is_broadcast, BroadcastPolicy, Mention, MentionResolver};
+use crate::snippet::{parse_info, Snippet};
+use crate::style::RenderStyle;
 
 use pulldown_cmark::Event::*;
-use pulldown_cmark::{CowStr, Event, Tag};
+use pulldown_cmark::{Alignment, CowStr, Event, LinkType, Tag};
+use unicode_width::UnicodeWidthStr;
 
-struct SlackdownWriter<'a, I, W> {
+/// Options controlling how Slackdown is rendered.
+#[derive(Debug, Clone)]
+pub struct SlackdownOptions {
+    /// Written before the alt text of every image link, e.g. `":frame_with_picture: "`.
+    pub image_prefix: String,
+    /// How footnote references and the labels of the notes are written.
+    pub footnote_style: FootnoteStyle,
+    /// Written in place of the bullet of a checked task list item.
+    pub task_checked: String,
+    /// Written in place of the bullet of an unchecked task list item.
+    pub task_unchecked: String,
+    /// Styles of headings and lists.
+    pub style: RenderStyle,
+    /// Turns `@user` and `#channel` handles in text into Slack mentions.
+    pub mentions: Option<Arc<dyn MentionResolver>>,
+    /// What to do with `@here`, `@channel` and `@everyone`.
+    pub broadcast: BroadcastPolicy,
+    /// How HTML tags in the Markdown are rendered.
+    pub html: HtmlPolicy,
+    /// Joins the lines of hard-wrapped paragraphs, so that Slack wraps them to
+    /// fit the screen instead. Hard line breaks are kept.
+    pub reflow: bool,
+    /// Written on a line of its own for a horizontal rule, since Slack has no
+    /// markup for one. An empty divider leaves just the blank line.
+    pub divider: String,
+    /// Writes the language of a code block as an italic label above it.
+    pub code_labels: bool,
+    /// Code blocks with more lines than this are taken out of the message as
+    /// snippets, leaving a reference to them in their place. Only applies to
+    /// `push_slackdown_snippets`, since other output has nowhere to put them.
+    pub snippet_threshold: Option<usize>,
+}
+
+impl Default for SlackdownOptions {
+    fn default() -> Self {
+        Self {
+            image_prefix: String::new(),
+            footnote_style: FootnoteStyle::default(),
+            task_checked: "☑".to_string(),
+            task_unchecked: "☐".to_string(),
+            style: RenderStyle::default(),
+            mentions: None,
+            broadcast: BroadcastPolicy::default(),
+            html: HtmlPolicy::default(),
+            reflow: false,
+            divider: "─".repeat(20),
+            code_labels: false,
+            snippet_threshold: None,
+        }
+    }
+}
+
+impl SlackdownOptions {
+    fn task_glyph(&self, checked: bool) -> &str {
+        if checked {
+            &self.task_checked
+        } else {
+            &self.task_unchecked
+        }
+    }
+}
+
+/// Style of footnote markers. Slack has no footnotes, so references become
+/// plain markers and the definitions are listed in a "Notes" section at the
+/// end of the message.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FootnoteStyle {
+    /// `[1]`
+    #[default]
+    Brackets,
+    /// `¹`
+    Superscript,
+}
+
+impl FootnoteStyle {
+    fn marker(self, number: usize) -> String {
+        match self {
+            FootnoteStyle::Brackets => format!("[{}]", number),
+            FootnoteStyle::Superscript => number
+                .to_string()
+                .chars()
+                .map(|c| SUPERSCRIPT_DIGITS[c.to_digit(10).unwrap() as usize])
+                .collect(),
+        }
+    }
+}
+
+const SUPERSCRIPT_DIGITS: [char; 10] = ['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'];
+
+/// Heading of the section that footnote definitions are collected into.
+const FOOTNOTES_HEADING: &str = "Notes";
+
+struct SlackdownWriter<'a, I, W>
+where
+    I: Iterator<Item = Event<'a>>,
+{
     /// Iterator supplying events.
-    iter: I,
+    iter: Peekable<I>,
 
     /// Writer to write to.
     writer: W,
 
+    options: SlackdownOptions,
+
     /// Whether or not the last write wrote a newline.
     end_newline: bool,
+    /// Whether or not the output ends with a blank line.
+    blank_line: bool,
+    /// Whether nothing has been written since the start of the message, or
+    /// of the container currently being rendered, like a list item or quote.
+    /// Blocks there don't need separating from what precedes them.
+    block_start: bool,
+    /// Number of newlines written to `writer` that are being held back until
+    /// something follows them, so that the message doesn't end with any.
+    held_newlines: usize,
 
-    /// Used to properly indent sub-lists
-    list_indent_lvl: usize,
-    /// Used to determine when converting ordered list, and
-    /// correctly number each item. 0 means not converting ordered list,
-    /// but instead doing unordered.
-    curr_ordered_list_item_num: usize,
+    /// Stack of the lists currently being rendered, innermost last.
+    lists: Vec<ListFrame>,
+    /// Whether text is uppercased, as is done for some heading styles.
+    uppercase_text: bool,
+    /// Whether the text of a link is being rendered.
+    in_link: bool,
     numbers: HashMap<CowStr<'a>, usize>,
+    /// Rendered footnote definitions, written at the end of the message.
+    footnotes: Vec<(CowStr<'a>, String)>,
+    /// Name of the footnote definition currently being captured, and the
+    /// state of the output before it started.
+    curr_footnote: Option<(CowStr<'a>, LineState)>,
+
+    /// Number of block quotes currently open.
+    quotes: usize,
+
+    /// Indentation of the lines of the blocks after the first in a list item.
+    block_indent: String,
+
+    /// Stack of buffers that output is redirected into while rendering spans
+    /// that have to be post-processed before being written, such as link text.
+    captures: Vec<Capture>,
+
+    /// Number of tags currently open.
+    depth: usize,
+    /// Number of bytes written to `writer`.
+    written: usize,
+    /// Offsets into the output at which top-level blocks end.
+    boundaries: Vec<usize>,
+
+    /// The table currently being rendered, if any.
+    table: Option<Table>,
+
+    /// Stack of the HTML links currently open, with the `href` of each, or
+    /// `None` for anchors without one.
+    html_links: Vec<Option<String>>,
+    /// Stack of the markup written for the HTML formatting tags currently
+    /// open, so that only those are closed.
+    html_marks: Vec<&'static str>,
+    /// Stack of the HTML lists currently open, with the number of the next
+    /// item, or `None` for an unordered list.
+    html_lists: Vec<Option<usize>>,
+    /// Whether the text of an HTML `<pre>` is being rendered.
+    in_pre: bool,
+    /// Whether HTML text ended with whitespace that is yet to be written.
+    html_space: bool,
+
+    /// Whether long code blocks are taken out of the message as snippets.
+    extract_snippets: bool,
+    /// Code blocks taken out of the message.
+    snippets: Vec<Snippet>,
+    /// Whether the code block being rendered was taken out as a snippet.
+    in_snippet: bool,
+
+    /// Whether plain text is written instead of mrkdwn, leaving out all
+    /// formatting and escaping.
+    plain: bool,
 }
 
 /// This wrapper exists because we can't have both a blanket implementation
@@ -78,8 +242,6 @@ struct WriteWrapper<W>(W);
 /// of `std::io::Write` in order to include `String`.
 pub(crate) trait StrWrite {
     fn write_str(&mut self, s: &str) -> io::Result<()>;
-
-    fn write_fmt(&mut self, args: Arguments) -> io::Result<()>;
 }
 
 impl<W> StrWrite for WriteWrapper<W>
@@ -90,25 +252,14 @@ where
     fn write_str(&mut self, s: &str) -> io::Result<()> {
         self.0.write_all(s.as_bytes())
     }
-
-    #[inline]
-    fn write_fmt(&mut self, args: Arguments) -> io::Result<()> {
-        self.0.write_fmt(args)
-    }
 }
 
-impl<'w> StrWrite for String {
+impl StrWrite for String {
     #[inline]
     fn write_str(&mut self, s: &str) -> io::Result<()> {
         self.push_str(s);
         Ok(())
     }
-
-    #[inline]
-    fn write_fmt(&mut self, args: Arguments) -> io::Result<()> {
-        // FIXME: translate fmt error to io error?
-        FmtWrite::write_fmt(self, args).map_err(|_| ErrorKind::Other.into())
-    }
 }
 
 impl<W> StrWrite for &'_ mut W
@@ -119,11 +270,6 @@ where
     fn write_str(&mut self, s: &str) -> io::Result<()> {
         (**self).write_str(s)
     }
-
-    #[inline]
-    fn write_fmt(&mut self, args: Arguments) -> io::Result<()> {
-        (**self).write_fmt(args)
-    }
 }
 
 impl<'a, I, W> SlackdownWriter<'a, I, W>
@@ -131,234 +277,939 @@ where
     I: Iterator<Item = Event<'a>>,
     W: StrWrite,
 {
-    fn new(iter: I, writer: W) -> Self {
+    fn new(iter: I, writer: W, options: SlackdownOptions) -> Self {
         Self {
-            iter,
+            iter: iter.peekable(),
             writer,
+            options,
             end_newline: true,
+            blank_line: false,
+            block_start: true,
+            held_newlines: 0,
             numbers: HashMap::new(),
-            list_indent_lvl: 0,
-            curr_ordered_list_item_num: 0,
+            footnotes: Vec::new(),
+            curr_footnote: None,
+            lists: Vec::new(),
+            uppercase_text: false,
+            in_link: false,
+            quotes: 0,
+            block_indent: String::new(),
+            captures: Vec::new(),
+            depth: 0,
+            written: 0,
+            boundaries: Vec::new(),
+            table: None,
+            html_links: Vec::new(),
+            html_marks: Vec::new(),
+            html_lists: Vec::new(),
+            in_pre: false,
+            html_space: false,
+            extract_snippets: false,
+            snippets: Vec::new(),
+            in_snippet: false,
+            plain: false,
         }
     }
 
     /// Writes a buffer, and tracks whether or not a newline was written.
     #[inline]
     fn write(&mut self, s: &str) -> io::Result<()> {
-        self.writer.write_str(s)?;
-
+        let indented;
+        // Link text and the like is indented once it's written, but the lines
+        // of a quote are prefixed as they are, so they're indented in it.
+        let capturing_span = self
+            .captures
+            .last()
+            .is_some_and(|c| c.quote_indent.is_none());
+        let s = if self.block_indent.is_empty() || capturing_span {
+            s
+        } else {
+            indented = self.indent_lines(s);
+            &indented
+        };
         if !s.is_empty() {
+            self.blank_line = s.ends_with("\n\n") || (s == "\n" && self.end_newline);
             self.end_newline = s.ends_with('\n');
+            self.block_start = false;
+        }
+
+        match self.captures.last_mut() {
+            Some(capture) => capture.text.push_str(s),
+            None => {
+                // Newlines are only written once something follows them, and
+                // never at the start, so the message has no surrounding blank lines.
+                let mut content = s.trim_end_matches('\n');
+                if self.written == 0 {
+                    content = content.trim_start_matches('\n');
+                }
+                if !content.is_empty() {
+                    if self.written > 0 {
+                        let held = "\n".repeat(self.held_newlines);
+                        self.writer.write_str(&held)?;
+                        self.written += held.len();
+                    }
+                    self.held_newlines = 0;
+                    self.writer.write_str(content)?;
+                    self.written += content.len();
+                }
+                self.held_newlines += s.len() - s.trim_end_matches('\n').len();
+            }
         }
         Ok(())
     }
 
+    /// Writes formatting, which is left out of plain text.
+    fn write_markup(&mut self, s: &str) -> io::Result<()> {
+        if self.plain {
+            Ok(())
+        } else {
+            self.write(s)
+        }
+    }
+
+    /// Writes text, escaping Slack's control characters.
+    fn write_escaped(&mut self, s: &str) -> io::Result<()> {
+        if self.plain {
+            // Plain text isn't escaped, so a `<!here>` in it is broken up
+            // instead, unless broadcasts are allowed.
+            if self.options.broadcast == BroadcastPolicy::Neutralize && s.contains("<!") {
+                return self.write(&s.replace("<!", "<\u{2060}!"));
+            }
+            return self.write(s);
+        }
+        let mut escaped = String::with_capacity(s.len());
+        escape_slack(&mut escaped, s)?;
+        self.write(&escaped)
+    }
+
+    /// Writes text, turning any handles that can be resolved into mentions.
+    /// Broadcasts like `@here` are always handled, according to the broadcast policy.
+    fn write_text(&mut self, text: &str) -> io::Result<()> {
+        // Mentions can't be nested in the text of a link, and plain text has
+        // none, though broadcasts are still handled.
+        let resolver = self
+            .options
+            .mentions
+            .clone()
+            .filter(|_| !self.in_link && !self.plain);
+        let notify = self.options.broadcast == BroadcastPolicy::Notify && !self.in_link;
+        let mut mark = 0;
+        for (start, end, mention) in find_mentions(text) {
+            let entity = match mention {
+                Mention::User(handle) if is_broadcast(handle) => Some(if notify {
+                    format!("<!{}>", handle.to_lowercase())
+                } else {
+                    // A word joiner keeps the text looking the same, but stops
+                    // Slack from treating it as a broadcast.
+                    format!("@\u{2060}{}", handle)
+                }),
+                Mention::User(handle) => resolver
+                    .as_ref()
+                    .and_then(|r| r.resolve_user(handle))
+                    .map(|id| format!("<@{}>", id)),
+                Mention::Channel(name) => resolver
+                    .as_ref()
+                    .and_then(|r| r.resolve_channel(name))
+                    .map(|id| format!("<#{}|{}>", id, name)),
+            };
+            if let Some(entity) = entity {
+                self.write_escaped(&text[mark..start])?;
+                self.write(&entity)?;
+                mark = end;
+            }
+        }
+        self.write_escaped(&text[mark..])
+    }
+
+    /// Indents each line of `s` that has any text by `block_indent`.
+    fn indent_lines(&self, s: &str) -> String {
+        let mut indented = String::with_capacity(s.len());
+        let mut line_start = self.end_newline;
+        for c in s.chars() {
+            if line_start && c != '\n' {
+                indented.push_str(&self.block_indent);
+            }
+            indented.push(c);
+            line_start = c == '\n';
+        }
+        indented
+    }
+
     /// Writes a new line.
     fn write_newline(&mut self) -> io::Result<()> {
-        self.end_newline = true;
         self.write("\n")
     }
 
-    pub fn run(mut self) -> io::Result<()> {
+    /// Writes a new line, unless the output already ends with one.
+    fn finish_line(&mut self) -> io::Result<()> {
+        if self.end_newline {
+            Ok(())
+        } else {
+            self.write_newline()
+        }
+    }
+
+    /// Separates a new block from whatever precedes it, by a blank line at the
+    /// top level and in loose lists, and by just a line break in tight ones.
+    /// Blocks after the first in a list item are indented to line up with its
+    /// text.
+    fn start_block(&mut self) -> io::Result<()> {
+        let Some(list) = self.lists.last() else {
+            return self.separate(true);
+        };
+        if self.block_start {
+            return Ok(());
+        }
+        let (loose, indent) = (list.loose, list.content_indent.clone());
+        self.separate(loose)?;
+        self.block_indent = indent;
+        Ok(())
+    }
+
+    /// Starts a new line, followed by a blank line if `blank` is set, unless
+    /// at the start of the message or of a container.
+    fn separate(&mut self, blank: bool) -> io::Result<()> {
+        if self.block_start {
+            return Ok(());
+        }
+        self.finish_line()?;
+        if blank && !self.blank_line {
+            self.write_newline()?;
+        }
+        Ok(())
+    }
+
+    fn line_state(&self) -> LineState {
+        LineState {
+            end_newline: self.end_newline,
+            blank_line: self.blank_line,
+            block_start: self.block_start,
+        }
+    }
+
+    fn restore_line_state(&mut self, state: LineState) {
+        self.end_newline = state.end_newline;
+        self.blank_line = state.blank_line;
+        self.block_start = state.block_start;
+    }
+
+    pub fn run(&mut self) -> io::Result<()> {
         while let Some(event) = self.iter.next() {
             match event {
                 Event::Start(tag) => {
+                    // These consume their own end tag.
+                    if !matches!(tag, Tag::Image(..) | Tag::TableCell) {
+                        self.depth += 1;
+                    }
                     self.start_tag(tag)?;
                 }
                 Event::End(tag) => {
                     self.end_tag(tag)?;
+                    self.depth = self.depth.saturating_sub(1);
+                    if self.depth == 0 {
+                        self.close_html()?;
+                        self.boundaries.push(self.written);
+                    }
                 }
                 Text(text) => {
-                    self.writer.write_str(&text)?;
-                    self.end_newline = text.ends_with('\n');
+                    // The parser splits text at characters like `<`, which would
+                    // hide a `<!here>` from the broadcast policy in plain text.
+                    let mut text = text.into_string();
+                    while let Some(Text(more)) = self.iter.peek() {
+                        text.push_str(more);
+                        self.iter.next();
+                    }
+                    if self.uppercase_text {
+                        self.write_text(&text.to_uppercase())?;
+                    } else {
+                        self.write_text(&text)?;
+                    }
                 }
                 Code(text) => {
-                    self.write("`")?;
-                    self.write(&text)?;
-                    self.write("`")?;
+                    self.write_markup("`")?;
+                    self.write_escaped(&text)?;
+                    self.write_markup("`")?;
                 }
                 Html(html) | InlineHtml(html) => {
-                    self.write(&html)?;
+                    self.write_html(&html)?;
                 }
                 SoftBreak => {
-                    self.write_newline()?;
+                    if self.options.reflow {
+                        self.write(" ")?;
+                    } else {
+                        self.write_newline()?;
+                    }
                 }
                 HardBreak => {
-                    self.write("<br />\n")?;
+                    self.write_newline()?;
                 }
                 FootnoteReference(name) => {
-                    let len = self.numbers.len() + 1;
-                    self.write("<sup class=\"footnote-reference\"><a href=\"#")?;
-                    self.write(&name)?;
-                    self.write("\">")?;
-                    let number = *self.numbers.entry(name).or_insert(len);
-                    write!(&mut self.writer, "{}", number)?;
-                    self.write("</a></sup>")?;
+                    let number = self.footnote_number(name);
+                    let marker = self.options.footnote_style.marker(number);
+                    self.write(&marker)?;
                 }
-                TaskListMarker(true) => {
-                    self.write("<input disabled=\"\" type=\"checkbox\" checked=\"\"/>\n")?;
-                }
-                TaskListMarker(false) => {
-                    self.write("<input disabled=\"\" type=\"checkbox\"/>\n")?;
+                TaskListMarker(checked) => {
+                    // Consumed along with the start of its list item.
+                    let glyph = self.options.task_glyph(checked).to_string();
+                    self.write(&glyph)?;
+                    self.write(" ")?;
                 }
             }
         }
-        Ok(())
+        self.write_footnotes()
     }
 
     /// Writes the start of an HTML tag.
     fn start_tag(&mut self, tag: Tag<'a>) -> io::Result<()> {
         match tag {
-            Tag::Paragraph => {
-                if self.end_newline {
-                    self.write("")
-                } else {
-                    self.write(" ")
-                }
+            Tag::Paragraph => self.start_block(),
+            Tag::Rule => self.write_divider(),
+            Tag::Header(level) => {
+                // Slack doesn't support headers, so they are styled instead, bold by default.
+                let style = self.options.style.heading(level);
+                self.uppercase_text = style.uppercase;
+                self.start_block()?;
+                self.write_markup(style.affixes().0)
             }
-            Tag::Rule => Ok(()),
-            Tag::Header(_level) => {
-                // Slack doesn't support headers, so just make bold.
-                if self.end_newline {
-                    self.end_newline = false;
-                    self.write("*")
-                } else {
-                    self.write("\n*")
+            Tag::Table(alignments) => {
+                self.start_block()?;
+                self.table = Some(Table {
+                    alignments,
+                    rows: Vec::new(),
+                    head_len: 0,
+                });
+                Ok(())
+            }
+            Tag::TableHead | Tag::TableRow => {
+                if let Some(table) = self.table.as_mut() {
+                    table.rows.push(Vec::new());
                 }
+                Ok(())
             }
-            Tag::Table(_) | Tag::TableHead | Tag::TableRow | Tag::TableCell => {
-                // TODO(Jonathon): Throw error to say this feature is unhandled
+            Tag::TableCell => {
+                // Formatting can't be shown inside the code block a table is
+                // rendered in, so only the cell's text is kept.
+                let text = self.collect_raw_text();
+                if let Some(row) = self.table.as_mut().and_then(|t| t.rows.last_mut()) {
+                    row.push(text.trim().to_string());
+                }
                 Ok(())
             }
             Tag::BlockQuote => {
-                if self.end_newline {
-                    self.write(">")
-                } else {
-                    self.write("\n>")
+                self.start_block()?;
+                self.quotes += 1;
+                // Slack can't nest quotes, so only the outermost is captured to
+                // have its lines prefixed, and any inside it are flattened into it.
+                if self.quotes == 1 {
+                    // The quote's lines are indented as a whole once it's
+                    // written, so inside it only its own lists indent.
+                    let outer_indent = std::mem::take(&mut self.block_indent);
+                    self.start_capture();
+                    if let Some(capture) = self.captures.last_mut() {
+                        capture.quote_indent = Some(outer_indent);
+                    }
+                    self.end_newline = true;
+                    self.block_start = true;
                 }
+                Ok(())
             }
-            Tag::CodeBlock(_info) => {
-                if !self.end_newline {
+            Tag::CodeBlock(info) => {
+                let mut code = String::new();
+                while let Some(Text(text)) = self.iter.peek() {
+                    code.push_str(text);
+                    self.iter.next();
+                }
+                let (language, filename) = parse_info(&info);
+                self.start_block()?;
+
+                let lines = code.lines().count();
+                if self.extract_snippets
+                    && self
+                        .options
+                        .snippet_threshold
+                        .is_some_and(|max| lines > max)
+                {
+                    let snippet = Snippet {
+                        language,
+                        filename,
+                        content: code,
+                    };
+                    let reference = snippet.reference(self.snippets.len() + 1);
+                    self.snippets.push(snippet);
+                    self.in_snippet = true;
+                    self.write_markup("_")?;
+                    self.write_escaped(&reference)?;
+                    return self.write_markup("_");
+                }
+
+                if let Some(language) = language.filter(|_| self.options.code_labels && !self.plain)
+                {
+                    // Slack doesn't highlight code, so the language is shown instead.
+                    self.write_markup("_")?;
+                    self.write_escaped(&language)?;
+                    self.write_markup("_")?;
                     self.write_newline()?;
                 }
-                self.write("```")
-            }
-            Tag::List(Some(1)) => {
-                self.list_indent_lvl += 1;
-                self.curr_ordered_list_item_num += 1;
-                Ok(())
+                self.write_markup("```")?;
+                // Code is shown as written, without mentions.
+                self.write_escaped(&code)
             }
-            Tag::List(Some(start)) => {
-                self.curr_ordered_list_item_num = start;
+            Tag::List(start) => {
+                self.start_block()?;
+                let depth = self.lists.len();
+                self.lists.push(ListFrame {
+                    next_number: start,
+                    depth,
+                    started: false,
+                    loose: false,
+                    content_indent: String::new(),
+                });
+                self.block_start = true;
                 Ok(())
             }
-            Tag::List(None) => {
-                self.list_indent_lvl += 1;
-                if self.end_newline {
-                    self.write("")
-                } else {
-                    self.write("\n")
-                }
-            }
             Tag::Item => {
-                let tabs = "    ".repeat(self.list_indent_lvl - 1);
-                self.write(&tabs).unwrap();
-                if self.curr_ordered_list_item_num > 0 {
-                    self.write(&format!("{}. ", self.curr_ordered_list_item_num))
-                } else {
-                    self.write("• ")
+                let (next_number, depth, started) = match self.lists.last() {
+                    Some(list) => (list.next_number, list.depth, list.started),
+                    None => (None, 0, false),
+                };
+                let task = match self.iter.peek() {
+                    Some(&TaskListMarker(checked)) => {
+                        self.iter.next();
+                        Some(self.options.task_glyph(checked).to_string())
+                    }
+                    _ => None,
+                };
+                // Items of loose lists, which have their text in paragraphs, are
+                // separated by blank lines.
+                let loose = matches!(self.iter.peek(), Some(Event::Start(Tag::Paragraph)));
+                // An item starts a new line even after an empty one.
+                if started {
+                    self.block_start = false;
+                }
+                self.separate(loose)?;
+                self.block_indent.clear();
+                let label = match (next_number, task) {
+                    (Some(number), Some(glyph)) => {
+                        format!("{} {}", self.options.style.number(depth, number), glyph)
+                    }
+                    (Some(number), None) => self.options.style.number(depth, number),
+                    // The checkbox replaces the bullet of a task list item.
+                    (None, Some(glyph)) => glyph,
+                    (None, None) => self.options.style.bullet(depth).to_string(),
+                };
+                let indent = self.options.style.indent;
+                let content_indent = indent.render(depth) + &indent.fill(label.width() + 1);
+                if let Some(list) = self.lists.last_mut() {
+                    list.started = true;
+                    list.loose = loose;
+                    list.content_indent = content_indent;
                 }
+                self.write(&indent.render(depth))?;
+                self.write(&label)?;
+                self.write(" ")?;
+                self.block_start = true;
+                Ok(())
             }
-            Tag::Emphasis => self.write("_"),
-            Tag::Strong => self.write("*"),
-            Tag::Strikethrough => self.write("~"),
-            Tag::Link(_link_type, _dest, _title) => {
-                // Don't write anything. We only want to write the hyperlink's text,
-                // not the link itself.
+            Tag::Emphasis => self.write_markup("_"),
+            Tag::Strong => self.write_markup("*"),
+            Tag::Strikethrough => self.write_markup("~"),
+            Tag::Link(_, _, _) => {
+                // The link text is captured so that it can be compared with the
+                // destination once the link ends.
+                self.start_capture();
+                self.in_link = true;
                 Ok(())
             }
-            Tag::Image(_link_type, dest, title) => {
-                self.write("<img src=\"")?;
-                escape_href(&mut self.writer, &dest)?;
-                self.write("\" alt=\"")?;
-                self.raw_text()?;
-                if !title.is_empty() {
-                    self.write("\" title=\"")?;
-                    self.write(&title)?;
-                }
-                self.write("\" />")
+            Tag::Image(_link_type, dest, _title) => {
+                let alt = self.collect_raw_text();
+                let alt = self.render_alt_text(&alt)?;
+                self.write_image(&dest, &alt)
             }
             Tag::FootnoteDefinition(name) => {
-                // TODO(Jonathon): Decide how to handle these
-                if self.end_newline {
-                    self.write("<div class=\"footnote-definition\" id=\"")?;
-                } else {
-                    self.write("\n<div class=\"footnote-definition\" id=\"")?;
-                }
-                self.write(&*name)?;
-                self.write("\"><sup class=\"footnote-definition-label\">")?;
-                let len = self.numbers.len() + 1;
-                let number = *self.numbers.entry(name).or_insert(len);
-                write!(&mut self.writer, "{}", number)?;
-                self.write("</sup>")
+                // Definitions are moved to the end of the message, so capture
+                // them instead of writing them where they appear.
+                self.curr_footnote = Some((name, self.line_state()));
+                self.start_capture();
+                self.block_start = true;
+                Ok(())
             }
-            Tag::HtmlBlock => Ok(()),
+            Tag::HtmlBlock => self.start_block(),
         }
     }
 
-    fn end_tag(&mut self, tag: Tag) -> io::Result<()> {
+    fn end_tag(&mut self, tag: Tag<'a>) -> io::Result<()> {
         match tag {
-            Tag::Paragraph => {
-                self.write("")?;
+            // Blocks are separated from what follows when the next one starts.
+            Tag::Paragraph | Tag::Rule => (),
+            Tag::Header(level) => {
+                let style = self.options.style.heading(level);
+                self.uppercase_text = false;
+                self.write_markup(style.affixes().1)?;
             }
-            Tag::Rule => (),
-            Tag::Header(_level) => {
-                // Slack doesn't support headers
-                self.write("*\n")?;
+            Tag::Table(_) => {
+                if let Some(table) = self.table.take() {
+                    self.write_table(table)?;
+                }
             }
-            Tag::Table(_) | Tag::TableHead | Tag::TableRow | Tag::TableCell => {}
+            Tag::TableHead => {
+                if let Some(table) = self.table.as_mut() {
+                    table.head_len = table.rows.len();
+                }
+            }
+            Tag::TableRow | Tag::TableCell => {}
             Tag::BlockQuote => {
-                self.write("\n")?;
+                self.quotes = self.quotes.saturating_sub(1);
+                if self.quotes == 0 {
+                    let outer_indent = self.captures.last_mut().and_then(|c| c.quote_indent.take());
+                    let text = self.end_capture()?;
+                    self.block_indent = outer_indent.unwrap_or_default();
+                    // The quote was started on a line of its own.
+                    self.end_newline = true;
+                    self.write_quote(&text)?;
+                }
             }
             Tag::CodeBlock(_) => {
-                self.write("```\n")?;
-            }
-            Tag::List(Some(_)) => {
-                self.curr_ordered_list_item_num = 0;
+                if !std::mem::take(&mut self.in_snippet) {
+                    self.write_markup("```")?;
+                }
             }
-            Tag::List(None) => {
-                self.list_indent_lvl -= 1;
-                self.write("")?;
+            Tag::List(_) => {
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.block_indent.clear();
+                }
             }
             Tag::Item => {
-                self.write("\n")?;
-                if self.curr_ordered_list_item_num > 0 {
-                    self.curr_ordered_list_item_num += 1;
+                if let Some(number) = self.lists.last_mut().and_then(|l| l.next_number.as_mut()) {
+                    *number += 1;
                 }
             }
             Tag::Emphasis => {
-                self.write("_")?;
+                self.write_markup("_")?;
             }
             Tag::Strong => {
-                self.write("*")?;
+                self.write_markup("*")?;
             }
             Tag::Strikethrough => {
-                self.write("~")?;
+                self.write_markup("~")?;
+            }
+            Tag::Link(link_type, dest, _title) => {
+                // Slack has no notion of link titles, so they are dropped.
+                let text = self.end_capture()?;
+                self.in_link = false;
+                self.write_link(link_type, &dest, &text)?;
             }
-            Tag::Link(_, _, _) => {}
             Tag::Image(_, _, _) => (), // shouldn't happen, handled in start
             Tag::FootnoteDefinition(_) => {
-                self.write("\n")?;
+                let text = self.end_capture()?;
+                if let Some((name, state)) = self.curr_footnote.take() {
+                    self.footnotes.push((name, text.trim().to_string()));
+                    self.restore_line_state(state);
+                }
+            }
+            Tag::HtmlBlock => {
+                self.html_space = false;
+                self.finish_line()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the number of a footnote, numbering footnotes in order of
+    /// first appearance.
+    fn footnote_number(&mut self, name: CowStr<'a>) -> usize {
+        let len = self.numbers.len() + 1;
+        *self.numbers.entry(name).or_insert(len)
+    }
+
+    /// Writes the collected footnote definitions as a "Notes" section.
+    fn write_footnotes(&mut self) -> io::Result<()> {
+        if self.footnotes.is_empty() {
+            return Ok(());
+        }
+        // Definitions are numbered by their first reference, and any that are
+        // never referenced come last.
+        let mut footnotes: Vec<(usize, String)> = std::mem::take(&mut self.footnotes)
+            .into_iter()
+            .map(|(name, text)| (self.footnote_number(name), text))
+            .collect();
+        footnotes.sort_by_key(|(number, _)| *number);
+
+        self.start_block()?;
+        self.write_markup("*")?;
+        self.write(FOOTNOTES_HEADING)?;
+        self.write_markup("*")?;
+        for (number, text) in footnotes {
+            self.write_newline()?;
+            let marker = self.options.footnote_style.marker(number);
+            self.write(&marker)?;
+            self.write(" ")?;
+            self.write(&text)?;
+        }
+        Ok(())
+    }
+
+    /// Writes a Slack link, `<url|text>`, or just `<url>` when the text would
+    /// only repeat the destination. Links to anything but an absolute URL, like
+    /// `#installation`, are written as just their text.
+    fn write_link(&mut self, link_type: LinkType, dest: &str, text: &str) -> io::Result<()> {
+        let url = match link_type {
+            LinkType::Email if !dest.starts_with("mailto:") => format!("mailto:{}", dest),
+            _ => dest.to_string(),
+        };
+        if !is_absolute_url(&url) {
+            // Slack would read `<!…>`, `<#…>` and `<@…>` as broadcasts, channels
+            // and users, and a relative destination means nothing outside of
+            // its own page.
+            let text = text.trim();
+            return if text.is_empty() {
+                self.write_escaped(dest)
+            } else {
+                self.write(text)
+            };
+        }
+        if self.plain {
+            return self.write_plain_link(&url, text);
+        }
+        let mut href = String::new();
+        escape_slack_href(&mut href, &url)?;
+        let mut shown_url = String::new();
+        escape_slack(&mut shown_url, &url)?;
+
+        let text = text.trim();
+        self.write("<")?;
+        self.write(&href)?;
+        if !text.is_empty() && text != shown_url {
+            self.write("|")?;
+            self.write(text)?;
+        }
+        self.write(">")
+    }
+
+    /// Writes a link as plain text, `text (url)`, or just the URL when the text
+    /// would only repeat it.
+    fn write_plain_link(&mut self, url: &str, text: &str) -> io::Result<()> {
+        let text = text.trim();
+        let shown_url = url.strip_prefix("mailto:").unwrap_or(url);
+        if !text.is_empty() && text != url && text != shown_url {
+            self.write(text)?;
+            self.write(" (")?;
+            self.write(url)?;
+            self.write(")")
+        } else {
+            self.write(shown_url)
+        }
+    }
+
+    /// Writes quoted text, with a `>` at the start of every line so that
+    /// Slack quotes all of it rather than just the first line.
+    fn write_quote(&mut self, text: &str) -> io::Result<()> {
+        for (i, line) in text.trim_matches('\n').split('\n').enumerate() {
+            if i > 0 {
+                self.write_newline()?;
+            }
+            if line.is_empty() {
+                self.write(">")?;
+            } else {
+                self.write("> ")?;
+                self.write(line)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_divider(&mut self) -> io::Result<()> {
+        self.start_block()?;
+        let divider = self.options.divider.clone();
+        self.write(&divider)
+    }
+
+    /// Writes a link to an image, since Slack can't show images inline. The
+    /// alt text must already be escaped.
+    fn write_image(&mut self, dest: &str, alt: &str) -> io::Result<()> {
+        if self.in_link {
+            // Slack links can't be nested, so an image that is itself linked,
+            // like a badge, is shown by its alt text.
+            return self.write(alt.trim());
+        }
+        let label = format!("{}{}", self.options.image_prefix, alt.trim());
+        if !is_absolute_url(dest) {
+            return self.write(label.trim_end());
+        }
+        if self.plain {
+            return self.write_plain_link(dest, &label);
+        }
+        let mut href = String::new();
+        escape_slack_href(&mut href, dest)?;
+        self.write("<")?;
+        self.write(&href)?;
+        if !label.trim().is_empty() {
+            self.write("|")?;
+            self.write(label.trim_end())?;
+        }
+        self.write(">")
+    }
+
+    /// Writes a fragment of HTML according to the HTML policy.
+    fn write_html(&mut self, html: &str) -> io::Result<()> {
+        match self.options.html {
+            HtmlPolicy::Escape => return self.write_escaped(html),
+            HtmlPolicy::Passthrough if self.options.broadcast == BroadcastPolicy::Notify => {
+                return self.write(html);
+            }
+            // Raw HTML mustn't be a way around the broadcast policy.
+            HtmlPolicy::Passthrough => {
+                let neutralized = html.replace("<!", "&lt;!").replace("&lt;!--", "<!--");
+                return self.write(&neutralized);
+            }
+            HtmlPolicy::Convert | HtmlPolicy::Strip => (),
+        }
+        for (token, raw) in tokenize(html) {
+            match token {
+                Token::Text(text) => self.write_html_text(text)?,
+                Token::Start { ref name, .. } => self.start_html_tag(name, &token, raw)?,
+                Token::End { ref name } => self.end_html_tag(name, raw)?,
+                Token::Comment => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes text from HTML, decoding its character references and, outside
+    /// of a `<pre>`, collapsing its whitespace as a browser would.
+    fn write_html_text(&mut self, text: &str) -> io::Result<()> {
+        let text = decode_entities(text);
+        if self.in_pre {
+            // As in a browser, a newline straight after `<pre>` isn't shown.
+            let text = match text.strip_prefix('\n') {
+                Some(rest) if self.end_newline => rest,
+                _ => &text,
+            };
+            return self.write_escaped(text);
+        }
+        let words: Vec<&str> = text.split_whitespace().collect();
+        if words.is_empty() {
+            self.html_space |= !text.is_empty();
+            return Ok(());
+        }
+        if text.starts_with(char::is_whitespace) {
+            self.html_space = true;
+        }
+        self.write_html_space()?;
+        self.write_text(&words.join(" "))?;
+        self.html_space = text.ends_with(char::is_whitespace);
+        Ok(())
+    }
+
+    /// Writes any whitespace left over from the last HTML text, unless at the
+    /// start of a line.
+    fn write_html_space(&mut self) -> io::Result<()> {
+        if std::mem::take(&mut self.html_space) && !self.end_newline {
+            self.write(" ")?;
+        }
+        Ok(())
+    }
+
+    fn start_html_tag(&mut self, name: &str, token: &Token, raw: &str) -> io::Result<()> {
+        if is_block_tag(name) {
+            self.html_space = false;
+            self.finish_line()?;
+        }
+        let strip = self.options.html == HtmlPolicy::Strip;
+        match name {
+            "br" => {
+                // The line break in the Markdown that usually follows a `<br>`
+                // would only add a blank line.
+                if let Some(SoftBreak) = self.iter.peek() {
+                    self.iter.next();
+                }
+                self.write_newline()
+            }
+            "pre" => {
+                self.in_pre = true;
+                if strip {
+                    Ok(())
+                } else {
+                    self.write_markup("```")
+                }
+            }
+            _ if strip => Ok(()),
+            "hr" => self.write_divider(),
+            "b" | "strong" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "summary" => {
+                self.open_html_mark("*")
+            }
+            "i" | "em" | "cite" | "var" => self.open_html_mark("_"),
+            "s" | "del" | "strike" => self.open_html_mark("~"),
+            "code" | "kbd" | "samp" | "tt" if !self.in_pre => self.open_html_mark("`"),
+            "a" => {
+                self.write_html_space()?;
+                let href = token.attr("href").map(str::to_string);
+                if href.is_some() {
+                    self.start_capture();
+                    self.in_link = true;
+                }
+                self.html_links.push(href);
+                Ok(())
+            }
+            "img" => match token.attr("src") {
+                Some(src) => {
+                    self.write_html_space()?;
+                    let alt = self.render_alt_text(token.attr("alt").unwrap_or(""))?;
+                    self.write_image(src, &alt)
+                }
+                None => Ok(()),
+            },
+            "ul" => {
+                self.html_lists.push(None);
+                Ok(())
+            }
+            "ol" => {
+                let start = token.attr("start").and_then(|n| n.parse().ok());
+                self.html_lists.push(Some(start.unwrap_or(1)));
+                Ok(())
+            }
+            "li" => {
+                let depth = self.html_lists.len().saturating_sub(1);
+                let indent = self.options.style.indent.render(depth);
+                self.write(&indent)?;
+                let label = match self.html_lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        self.options.style.number(depth, *number - 1)
+                    }
+                    _ => self.options.style.bullet(depth).to_string(),
+                };
+                self.write(&label)?;
+                self.write(" ")
+            }
+            _ if is_known_tag(name) => Ok(()),
+            // Tags with no Slack equivalent are shown as they are, which keeps
+            // text like `Vec<String>` intact.
+            _ => {
+                self.write_html_space()?;
+                self.write_escaped(raw)
+            }
+        }
+    }
+
+    fn end_html_tag(&mut self, name: &str, raw: &str) -> io::Result<()> {
+        let strip = self.options.html == HtmlPolicy::Strip;
+        match name {
+            "pre" => {
+                self.in_pre = false;
+                if !strip {
+                    self.finish_line()?;
+                    self.write_markup("```")?;
+                }
+            }
+            _ if strip => (),
+            "b" | "strong" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "summary" => {
+                self.close_html_mark("*")?;
+            }
+            "i" | "em" | "cite" | "var" => self.close_html_mark("_")?,
+            "s" | "del" | "strike" => self.close_html_mark("~")?,
+            "code" | "kbd" | "samp" | "tt" if !self.in_pre => self.close_html_mark("`")?,
+            // Links opened outside of the current capture, such as before a
+            // quote, can't be closed inside it.
+            "a" if self.html_links.len() > self.capture_links() => {
+                if let Some(Some(href)) = self.html_links.pop() {
+                    let text = self.end_capture()?;
+                    self.in_link = false;
+                    self.write_link(LinkType::Inline, &href, &text)?;
+                }
+            }
+            "ul" | "ol" => {
+                self.html_lists.pop();
+            }
+            _ if is_known_tag(name) => (),
+            _ => self.write_escaped(raw)?,
+        }
+        if is_block_tag(name) {
+            self.html_space = false;
+            self.finish_line()?;
+        }
+        Ok(())
+    }
+
+    fn open_html_mark(&mut self, mark: &'static str) -> io::Result<()> {
+        self.write_html_space()?;
+        self.html_marks.push(mark);
+        self.write_markup(mark)
+    }
+
+    /// Closes the formatting of an HTML tag, unless no such tag is open.
+    fn close_html_mark(&mut self, mark: &'static str) -> io::Result<()> {
+        match self.html_marks.iter().rposition(|&m| m == mark) {
+            Some(i) => {
+                self.html_marks.remove(i);
+                self.write_markup(mark)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Closes any HTML elements left open at the end of a top-level block, so
+    /// that their contents aren't lost.
+    fn close_html(&mut self) -> io::Result<()> {
+        self.close_html_links(0)?;
+        while let Some(mark) = self.html_marks.pop() {
+            self.write_markup(mark)?;
+        }
+        self.html_lists.clear();
+        self.in_pre = false;
+        self.html_space = false;
+        Ok(())
+    }
+
+    /// Closes the HTML links left open beyond the first `depth`, writing their
+    /// text without a link.
+    fn close_html_links(&mut self, depth: usize) -> io::Result<()> {
+        while self.html_links.len() > depth {
+            if let Some(Some(_)) = self.html_links.pop() {
+                let text = self.captures.pop().map(|c| c.text).unwrap_or_default();
+                self.in_link = false;
+                self.write(&text)?;
             }
-            Tag::HtmlBlock => {}
         }
         Ok(())
     }
 
-    // run raw text, consuming end tag
-    fn raw_text(&mut self) -> io::Result<()> {
+    fn start_capture(&mut self) {
+        self.captures.push(Capture {
+            text: String::new(),
+            html_links: self.html_links.len(),
+            quote_indent: None,
+        });
+    }
+
+    /// Ends the innermost capture, closing any HTML links opened inside it,
+    /// and returns its text.
+    fn end_capture(&mut self) -> io::Result<String> {
+        self.close_html_links(self.capture_links())?;
+        Ok(self.captures.pop().map(|c| c.text).unwrap_or_default())
+    }
+
+    /// Number of HTML links that were open when the innermost capture started.
+    fn capture_links(&self) -> usize {
+        self.captures.last().map_or(0, |c| c.html_links)
+    }
+
+    /// Renders the alt text of an image, which becomes the text of a link, so
+    /// broadcasts in it are neutralized as they are in link text.
+    fn render_alt_text(&mut self, alt: &str) -> io::Result<String> {
+        let in_link = std::mem::replace(&mut self.in_link, true);
+        self.start_capture();
+        self.write_text(alt)?;
+        let rendered = self.end_capture()?;
+        self.in_link = in_link;
+        Ok(rendered)
+    }
+
+    /// Collects the unformatted, unescaped text up to the end tag, consuming it.
+    fn collect_raw_text(&mut self) -> String {
+        let mut out = String::new();
         let mut nest = 0;
-        while let Some(event) = self.iter.next() {
+        for event in self.iter.by_ref() {
             match event {
                 Event::Start(_) => nest += 1,
                 Event::End(_) => {
@@ -367,32 +1218,205 @@ where
                     }
                     nest -= 1;
                 }
-                Html(_) => (),
-                InlineHtml(text) | Code(text) | Text(text) => {
-                    self.write(&text)?;
-                    self.end_newline = text.ends_with('\n');
-                }
-                SoftBreak | HardBreak => {
-                    self.write(" ")?;
-                }
+                Html(html) | InlineHtml(html) => out.push_str(&text_content(&html)),
+                Code(text) | Text(text) => out.push_str(&text),
+                SoftBreak | HardBreak => out.push(' '),
                 FootnoteReference(name) => {
                     let len = self.numbers.len() + 1;
                     let number = *self.numbers.entry(name).or_insert(len);
-                    write!(&mut self.writer, "[{}]", number)?;
+                    out.push_str(&self.options.footnote_style.marker(number));
                 }
-                TaskListMarker(true) => self.write("[x]")?,
-                TaskListMarker(false) => self.write("[ ]")?,
+                TaskListMarker(checked) => out.push_str(self.options.task_glyph(checked)),
             }
         }
-        Ok(())
+        out
+    }
+
+    /// Writes a table as a grid of padded columns inside a code block, since
+    /// Slack has no table support of its own.
+    fn write_table(&mut self, table: Table) -> io::Result<()> {
+        let num_cols = table.rows.iter().map(Vec::len).max().unwrap_or(0);
+        let mut widths = vec![0; num_cols];
+        for row in &table.rows {
+            for (i, cell) in row.iter().enumerate() {
+                widths[i] = widths[i].max(cell.width());
+            }
+        }
+
+        let mut grid = String::new();
+        for (row_idx, row) in table.rows.iter().enumerate() {
+            let mut line = String::new();
+            for (i, width) in widths.iter().enumerate() {
+                if i > 0 {
+                    line.push_str(" | ");
+                }
+                let cell = row.get(i).map(String::as_str).unwrap_or("");
+                let alignment = table.alignments.get(i).unwrap_or(&Alignment::None);
+                line.push_str(&pad_cell(cell, *width, alignment));
+            }
+            grid.push_str(line.trim_end());
+            grid.push('\n');
+
+            if row_idx + 1 == table.head_len {
+                let rule: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+                grid.push_str(&rule.join("-+-"));
+                grid.push('\n');
+            }
+        }
+
+        self.write_markup("```\n")?;
+        self.write_escaped(&grid)?;
+        self.write_markup("```")
     }
 }
 
+/// Where the output stands in the layout of blocks, saved while output is
+/// captured elsewhere.
+struct LineState {
+    end_newline: bool,
+    blank_line: bool,
+    block_start: bool,
+}
+
+/// Output captured while rendering a span, along with the number of HTML links
+/// open when it started, so that any opened inside it are closed with it.
+struct Capture {
+    text: String,
+    html_links: usize,
+    /// For a quote, the indentation of the blocks around it, restored once it
+    /// ends.
+    quote_indent: Option<String>,
+}
+
+/// A list being rendered, tracked on a stack so that lists of any kind can be
+/// nested within each other.
+struct ListFrame {
+    /// Number of the next item, or `None` for an unordered list.
+    next_number: Option<usize>,
+    /// Number of lists this one is nested in.
+    depth: usize,
+    /// Whether an item of the list has been written.
+    started: bool,
+    /// Whether the current item's blocks are separated by blank lines.
+    loose: bool,
+    /// Indentation that lines up with the text of the current item.
+    content_indent: String,
+}
+
+/// A table whose cells are being collected so that the columns can be aligned
+/// once every row is known.
+struct Table {
+    alignments: Vec<Alignment>,
+    rows: Vec<Vec<String>>,
+    /// Number of rows belonging to the table head.
+    head_len: usize,
+}
+
+/// Whether a link destination is an absolute URL, starting with a scheme like
+/// `https:` or `mailto:`.
+pub(crate) fn is_absolute_url(dest: &str) -> bool {
+    match dest.split_once(':') {
+        Some((scheme, rest)) => {
+            !rest.is_empty()
+                && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        }
+        None => false,
+    }
+}
+
+/// Pads `cell` to `width` columns of display width, honoring the alignment.
+fn pad_cell(cell: &str, width: usize, alignment: &Alignment) -> String {
+    let padding = width.saturating_sub(cell.width());
+    let (left, right) = match alignment {
+        Alignment::Right => (padding, 0),
+        Alignment::Center => (padding / 2, padding - padding / 2),
+        Alignment::Left | Alignment::None => (0, padding),
+    };
+    format!("{}{}{}", " ".repeat(left), cell, " ".repeat(right))
+}
+
 pub fn push_slackdown<'a, I>(s: &mut String, iter: I)
 where
     I: Iterator<Item = Event<'a>>,
 {
-    SlackdownWriter::new(iter, s).run().unwrap();
+    push_slackdown_ext(s, iter, &SlackdownOptions::default());
+}
+
+pub fn push_slackdown_ext<'a, I>(s: &mut String, iter: I, options: &SlackdownOptions)
+where
+    I: Iterator<Item = Event<'a>>,
+{
+    SlackdownWriter::new(iter, s, options.clone())
+        .run()
+        .unwrap();
+}
+
+/// Like `push_slackdown_ext`, but numbers footnotes using, and updating, `numbers`
+/// so that footnotes are numbered consistently across several calls.
+pub(crate) fn push_slackdown_numbered<'a, I>(
+    s: &mut String,
+    iter: I,
+    options: &SlackdownOptions,
+    numbers: &mut HashMap<CowStr<'a>, usize>,
+) where
+    I: Iterator<Item = Event<'a>>,
+{
+    let mut writer = SlackdownWriter::new(iter, s, options.clone());
+    writer.numbers = std::mem::take(numbers);
+    writer.run().unwrap();
+    *numbers = writer.numbers;
+}
+
+/// Like `push_slackdown_ext`, but also returns the code blocks that were taken
+/// out of the message as snippets, according to `options.snippet_threshold`.
+pub fn push_slackdown_snippets<'a, I>(
+    s: &mut String,
+    iter: I,
+    options: &SlackdownOptions,
+) -> Vec<Snippet>
+where
+    I: Iterator<Item = Event<'a>>,
+{
+    let mut writer = SlackdownWriter::new(iter, s, options.clone());
+    writer.extract_snippets = true;
+    writer.run().unwrap();
+    writer.snippets
+}
+
+/// Renders the events as plain text, leaving out all formatting. Links are
+/// written as `text (url)`.
+pub fn push_plain_ext<'a, I>(s: &mut String, iter: I, options: &SlackdownOptions)
+where
+    I: Iterator<Item = Event<'a>>,
+{
+    let mut writer = SlackdownWriter::new(iter, s, options.clone());
+    writer.plain = true;
+    writer.run().unwrap();
+}
+
+/// Renders the events, returning the output split into top-level blocks. The
+/// blocks concatenate to exactly what `push_slackdown_ext` would produce.
+pub(crate) fn push_slackdown_blocks<'a, I>(iter: I, options: &SlackdownOptions) -> Vec<String>
+where
+    I: Iterator<Item = Event<'a>>,
+{
+    let mut output = String::new();
+    let mut writer = SlackdownWriter::new(iter, &mut output, options.clone());
+    writer.run().unwrap();
+    let boundaries = std::mem::take(&mut writer.boundaries);
+
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    for end in boundaries.into_iter().chain(Some(output.len())) {
+        if end > start {
+            blocks.push(output[start..end].to_string());
+            start = end;
+        }
+    }
+    blocks
 }
 
 pub fn write_slackdown<'a, I, W>(writer: W, iter: I) -> io::Result<()>
@@ -400,5 +1424,17 @@ where
     I: Iterator<Item = Event<'a>>,
     W: Write,
 {
-    SlackdownWriter::new(iter, WriteWrapper(writer)).run()
+    write_slackdown_ext(writer, iter, &SlackdownOptions::default())
+}
+
+pub fn write_slackdown_ext<'a, I, W>(
+    writer: W,
+    iter: I,
+    options: &SlackdownOptions,
+) -> io::Result<()>
+where
+    I: Iterator<Item = Event<'a>>,
+    W: Write,
+{
+    SlackdownWriter::new(iter, WriteWrapper(writer), options.clone()).run()
 }
@@ -0,0 +1,97 @@
+//! Code blocks extracted from a message, so that they can be uploaded to Slack
+//! as file snippets instead of crowding the message.
+
+use serde::Serialize;
+
+/// A code block taken out of a message, which is left with a reference to it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Snippet {
+    /// Language named by the code block's info string, like `rust`.
+    pub language: Option<String>,
+    /// File name given by the info string, like `main.rs` in ```` ```rust:main.rs ````
+    /// or ```` ```rust title="main.rs" ````.
+    pub filename: Option<String>,
+    /// The code, without any escaping.
+    pub content: String,
+}
+
+impl Snippet {
+    /// Returns the line that replaces the snippet in the message.
+    pub(crate) fn reference(&self, number: usize) -> String {
+        match (&self.filename, &self.language) {
+            (Some(filename), _) => format!("Snippet {}: {}", number, filename),
+            (None, Some(language)) => format!("Snippet {} ({})", number, language),
+            (None, None) => format!("Snippet {}", number),
+        }
+    }
+}
+
+/// Splits the info string of a code block into its language and file name.
+pub(crate) fn parse_info(info: &str) -> (Option<String>, Option<String>) {
+    let mut words = info.split_whitespace();
+    let (mut language, mut filename) = match words.next() {
+        Some(first) => match first.split_once(':') {
+            Some((language, filename)) => (non_empty(language), non_empty(filename)),
+            None if looks_like_file(first) => (None, Some(first.to_string())),
+            None => (Some(first.to_string()), None),
+        },
+        None => (None, None),
+    };
+    for word in words {
+        let value = ["title=", "file=", "filename="]
+            .iter()
+            .find_map(|key| word.strip_prefix(key))
+            .map(|value| value.trim_matches(['"', '\'']));
+        match value {
+            Some(value) => filename = non_empty(value),
+            None if filename.is_none() && looks_like_file(word) => {
+                filename = Some(word.to_string())
+            }
+            None => (),
+        }
+    }
+    // Attributes like `{.rust}` aren't languages.
+    if language.as_deref().is_some_and(|l| l.starts_with('{')) {
+        language = None;
+    }
+    (language, filename)
+}
+
+fn looks_like_file(word: &str) -> bool {
+    !word.starts_with(['.', '{']) && (word.contains('.') || word.contains('/'))
+}
+
+fn non_empty(s: &str) -> Option<String> {
+    Some(s.to_string()).filter(|s| !s.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(language: Option<&str>, filename: Option<&str>) -> (Option<String>, Option<String>) {
+        (language.map(String::from), filename.map(String::from))
+    }
+
+    #[test]
+    fn test_parse_info() {
+        assert_eq!(parse_info(""), parsed(None, None));
+        assert_eq!(parse_info("rust"), parsed(Some("rust"), None));
+        assert_eq!(
+            parse_info("rust:src/main.rs"),
+            parsed(Some("rust"), Some("src/main.rs"))
+        );
+        assert_eq!(
+            parse_info("python title=\"app.py\""),
+            parsed(Some("python"), Some("app.py"))
+        );
+        assert_eq!(
+            parse_info("toml Cargo.toml"),
+            parsed(Some("toml"), Some("Cargo.toml"))
+        );
+        assert_eq!(
+            parse_info("Dockerfile.dev"),
+            parsed(None, Some("Dockerfile.dev"))
+        );
+    }
+}
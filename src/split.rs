@@ -0,0 +1,358 @@
+//! Splits Slackdown into several messages that each fit within Slack's limits
+//! on the length of a message.
+
+use pulldown_cmark::Event;
+
+use crate::slackdown::{push_slackdown_blocks, SlackdownOptions};
+
+/// Slack's recommended maximum length of the text of a message. Longer messages
+/// are truncated.
+pub const DEFAULT_CHUNK_BUDGET: usize = 4000;
+
+const FENCE: &str = "```";
+const QUOTE: &str = "> ";
+
+/// Renders the events to Slackdown, split into chunks of at most `budget`
+/// characters.
+///
+/// Chunks are split between top-level blocks where possible, otherwise between
+/// lines, and as a last resort between words. Code blocks and quotes that are
+/// split are closed at the end of one chunk and reopened at the start of the next.
+pub fn split_slackdown<'a, I>(iter: I, options: &SlackdownOptions, budget: usize) -> Vec<String>
+where
+    I: Iterator<Item = Event<'a>>,
+{
+    let mut chunks = Chunks::new(budget);
+    for block in push_slackdown_blocks(iter, options) {
+        chunks.push_block(&block);
+    }
+    chunks.finish()
+}
+
+/// Splits one block of Slackdown, such as the text of a section, into chunks of
+/// at most `budget` characters, closing and reopening any code fence or quote
+/// that is split.
+pub(crate) fn split_block(block: &str, budget: usize) -> Vec<String> {
+    let mut chunks = Chunks::new(budget);
+    chunks.push_block(block);
+    chunks.finish()
+}
+
+struct Chunks {
+    budget: usize,
+    chunks: Vec<String>,
+    current: String,
+}
+
+impl Chunks {
+    fn new(budget: usize) -> Self {
+        Self {
+            // Leave room for a reopened code fence or quote.
+            budget: budget.max((QUOTE.len() + FENCE.len()) * 2 + 2),
+            chunks: Vec::new(),
+            current: String::new(),
+        }
+    }
+
+    fn push_block(&mut self, block: &str) {
+        if len(&self.current) + len(block) <= self.budget {
+            self.current.push_str(block);
+            return;
+        }
+        self.flush();
+        // Drop the blank line separating the block from the last chunk.
+        let block = block.trim_start_matches('\n');
+        if len(block) <= self.budget {
+            self.current.push_str(block);
+        } else {
+            self.push_lines(block);
+        }
+    }
+
+    /// Splits a block that is too long for one chunk between its lines.
+    fn push_lines(&mut self, block: &str) {
+        // Every line of a quote is already prefixed, but the prefix has to be
+        // carried over to lines that are split, and to reopened code fences.
+        let prefix = if block.starts_with('>') { QUOTE } else { "" };
+        let mut in_code = false;
+        for line in block.split_inclusive('\n') {
+            let closing = if in_code {
+                prefix.len() + FENCE.len()
+            } else {
+                0
+            };
+            if !self.current.is_empty() && len(&self.current) + len(line) + closing > self.budget {
+                if in_code {
+                    self.close_fence(prefix);
+                }
+                self.flush();
+                if in_code {
+                    self.current.push_str(prefix);
+                    self.current.push_str(FENCE);
+                    self.current.push('\n');
+                }
+            }
+
+            let toggles = line.matches(FENCE).count() % 2 == 1;
+            if len(&self.current) + len(line) + closing <= self.budget {
+                self.current.push_str(line);
+            } else if toggles && !in_code {
+                // The code that follows an opening fence is split as code.
+                let code = line.find(FENCE).unwrap() + FENCE.len();
+                self.current.push_str(&line[..code]);
+                self.push_words(&line[code..], prefix, true);
+            } else {
+                self.push_words(line, prefix, in_code);
+            }
+
+            if toggles {
+                in_code = !in_code;
+            }
+        }
+    }
+
+    /// Splits a line that is too long for one chunk between its words.
+    fn push_words(&mut self, line: &str, prefix: &str, in_code: bool) {
+        let mut rest = line;
+        if let Some(unquoted) = rest.strip_prefix(prefix).filter(|_| !prefix.is_empty()) {
+            self.current.push_str(prefix);
+            rest = unquoted;
+        }
+        // Spans still open from the last chunk, which were reopened in this one.
+        let mut spans = Spans::default();
+        loop {
+            let reserve = if in_code { FENCE.len() } else { 0 };
+            let room = self.budget.saturating_sub(len(&self.current) + reserve);
+            if len(rest) <= room {
+                self.current.push_str(rest);
+                return;
+            }
+            let (mut split, mut open) = spans.split(rest, room);
+            let closers = open.closers();
+            if !closers.is_empty() {
+                // Make room to close the spans that the split lands in.
+                (split, open) = spans.split(rest, room.saturating_sub(closers.len()).max(1));
+            }
+            if in_code {
+                // Code is shown as written, so has no spans to close.
+                open = Spans::default();
+            }
+            self.current.push_str(&rest[..split]);
+            self.current.push_str(&open.closers());
+            rest = rest[split..].trim_start_matches(' ');
+            if in_code {
+                self.current.push_str(FENCE);
+            }
+            self.flush();
+            self.current.push_str(prefix);
+            if in_code {
+                self.current.push_str(FENCE);
+            }
+            self.current.push_str(&open.openers());
+            spans = open;
+        }
+    }
+
+    /// Closes a code block that is split, on a line of its own if the last
+    /// one is finished.
+    fn close_fence(&mut self, prefix: &str) {
+        if self.current.ends_with('\n') {
+            self.current.push_str(prefix);
+        }
+        self.current.push_str(FENCE);
+    }
+
+    fn flush(&mut self) {
+        let chunk = self.current.trim();
+        if !chunk.is_empty() {
+            self.chunks.push(chunk.to_string());
+        }
+        self.current.clear();
+    }
+
+    fn finish(mut self) -> Vec<String> {
+        self.flush();
+        self.chunks
+    }
+}
+
+fn len(s: &str) -> usize {
+    s.chars().count()
+}
+
+/// Finds the byte offset at which to split `line` so that the first part has
+/// at most `room` characters. Prefers a space outside of any link, code span or
+/// formatting span, then any space outside a link, and otherwise splits mid-word.
+pub(crate) fn split_point(line: &str, room: usize) -> usize {
+    Spans::default().split(line, room).0
+}
+
+/// The links, code spans and formatting spans open at a point in a line.
+#[derive(Debug, Clone, Default)]
+struct Spans {
+    in_link: bool,
+    in_code: bool,
+    /// Formatting markers, outermost first.
+    open: Vec<char>,
+}
+
+impl Spans {
+    /// Finds where to split `line`, which starts with these spans open, as
+    /// `split_point` does, and returns the spans open at the split.
+    fn split(&self, line: &str, room: usize) -> (usize, Spans) {
+        let chars: Vec<(usize, char)> = line.char_indices().collect();
+        let hard = chars.get(room).map_or(line.len(), |&(i, _)| i);
+
+        let mut best_safe = None;
+        let mut best_outside_link = None;
+        let mut spans = self.clone();
+        for (n, &(i, c)) in chars.iter().enumerate().take(room + 1) {
+            if c == ' ' && n > 0 && !spans.in_link {
+                best_outside_link = Some((i, spans.clone()));
+                if !spans.in_code && spans.open.is_empty() {
+                    best_safe = Some((i, spans.clone()));
+                }
+            }
+            let prev = if n > 0 { Some(chars[n - 1].1) } else { None };
+            let next = chars.get(n + 1).map(|&(_, c)| c);
+            spans.step(prev, c, next);
+        }
+        best_safe.or(best_outside_link).unwrap_or_else(|| {
+            let mut spans = self.clone();
+            for (n, &(_, c)) in chars.iter().enumerate().take(room) {
+                let prev = if n > 0 { Some(chars[n - 1].1) } else { None };
+                spans.step(prev, c, chars.get(n + 1).map(|&(_, c)| c));
+            }
+            (hard, spans)
+        })
+    }
+
+    fn step(&mut self, prev: Option<char>, c: char, next: Option<char>) {
+        match c {
+            '<' if !self.in_code => self.in_link = true,
+            '>' if self.in_link => self.in_link = false,
+            '`' if !self.in_link => self.in_code = !self.in_code,
+            '*' | '_' | '~' if !self.in_link && !self.in_code => {
+                if self.open.last() == Some(&c) && prev.is_some_and(|p| !p.is_whitespace()) {
+                    self.open.pop();
+                } else if prev.is_none_or(|p| !p.is_alphanumeric())
+                    && next.is_some_and(|n| !n.is_whitespace())
+                {
+                    self.open.push(c);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// The markers that close the open spans, innermost first.
+    fn closers(&self) -> String {
+        let code = if self.in_code { "`" } else { "" };
+        code.chars()
+            .chain(self.open.iter().rev().copied())
+            .collect()
+    }
+
+    /// The markers that reopen the open spans.
+    fn openers(&self) -> String {
+        let code = if self.in_code { "`" } else { "" };
+        self.open.iter().copied().chain(code.chars()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pulldown_cmark::Parser;
+
+    fn split(markdown: &str, budget: usize) -> Vec<String> {
+        split_slackdown(Parser::new(markdown), &SlackdownOptions::default(), budget)
+    }
+
+    #[test]
+    fn test_splits_between_blocks() {
+        let chunks = split(
+            "# One\n\nFirst paragraph.\n\n# Two\n\nSecond paragraph.",
+            25,
+        );
+        assert_eq!(
+            chunks,
+            vec!["*One*\n\nFirst paragraph.", "*Two*\n\nSecond paragraph."]
+        );
+    }
+
+    #[test]
+    fn test_reopens_code_blocks() {
+        let chunks = split("```\nline one\nline two\nline three\n```", 30);
+        assert_eq!(
+            chunks,
+            vec!["```line one\nline two\n```", "```\nline three\n```"]
+        );
+    }
+
+    #[test]
+    fn test_never_splits_inside_links_or_formatting() {
+        let chunks = split(
+            "Start **bold words here** then [a link](https://example.com) end",
+            30,
+        );
+        assert_eq!(
+            chunks,
+            vec![
+                "Start *bold words here* then",
+                "<https://example.com|a link>",
+                "end"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reopens_long_formatting() {
+        let quote = "> **bold text that is long enough to need several chunks here**";
+        assert_eq!(
+            split(quote, 30),
+            vec![
+                "> *bold text that is long*",
+                "> *enough to need several*",
+                "> *chunks here*",
+            ]
+        );
+
+        let chunks = split("plain **bold text with `several chunks` here** end", 25);
+        assert_eq!(
+            chunks,
+            vec![
+                "plain",
+                "*bold text with*",
+                "*`several chunks` here*",
+                "end"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reopens_quotes() {
+        let quote = "> one two three four five six seven eight nine ten eleven twelve thirteen \
+                     fourteen fifteen sixteen seventeen eighteen nineteen twenty";
+        let chunks = split(quote, 60);
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            assert!(chunk.starts_with("> "), "{:?}", chunk);
+        }
+
+        let chunks = split("> ```\n> line one is here\n> line two is here\n> ```", 40);
+        assert_eq!(
+            chunks,
+            vec![
+                "> ```line one is here\n> ```",
+                "> ```\n> line two is here\n> ```"
+            ]
+        );
+
+        let chunks = split("> ```\n> aaaa bbbb cccc dddd eeee ffff gggg\n> ```", 30);
+        assert_eq!(
+            chunks,
+            vec!["> ```aaaa bbbb cccc dddd```", "> ```eeee ffff gggg\n> ```"]
+        );
+    }
+}
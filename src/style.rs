@@ -0,0 +1,202 @@
+//! Styles for the Markdown constructs that Slack has no equivalent of, such as
+//! headings and nested lists.
+
+/// How headings, bullets and list indentation are written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderStyle {
+    /// Heading styles by level, starting at `#`. Deeper headings use the last style.
+    pub headings: Vec<HeadingStyle>,
+    /// Bullets of unordered lists by nesting depth, repeating once exhausted.
+    pub bullets: Vec<String>,
+    /// Numbering of ordered lists by nesting depth, repeating once exhausted.
+    pub numbering: Vec<Numbering>,
+    /// Indentation of each level of nested lists.
+    pub indent: Indent,
+}
+
+impl Default for RenderStyle {
+    fn default() -> Self {
+        Self {
+            headings: vec![HeadingStyle::default()],
+            bullets: vec!["•".to_string()],
+            numbering: vec![Numbering::Decimal],
+            indent: Indent::Spaces(4),
+        }
+    }
+}
+
+impl RenderStyle {
+    pub(crate) fn heading(&self, level: i32) -> HeadingStyle {
+        let idx = (level.max(1) as usize - 1).min(self.headings.len().saturating_sub(1));
+        self.headings.get(idx).cloned().unwrap_or_default()
+    }
+
+    pub(crate) fn bullet(&self, depth: usize) -> &str {
+        match self.bullets.len() {
+            0 => "•",
+            len => &self.bullets[depth % len],
+        }
+    }
+
+    pub(crate) fn number(&self, depth: usize, number: usize) -> String {
+        let numbering = match self.numbering.len() {
+            0 => Numbering::Decimal,
+            len => self.numbering[depth % len],
+        };
+        numbering.format(number)
+    }
+}
+
+/// How a heading is written, since Slack has no headings of its own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeadingStyle {
+    /// Template for the heading, where `{}` is replaced by its text,
+    /// e.g. `":mega: *{}*"`.
+    pub template: String,
+    /// Whether the text of the heading is uppercased.
+    pub uppercase: bool,
+}
+
+impl Default for HeadingStyle {
+    fn default() -> Self {
+        Self {
+            template: "*{}*".to_string(),
+            uppercase: false,
+        }
+    }
+}
+
+impl HeadingStyle {
+    pub fn new(template: &str) -> Self {
+        Self {
+            template: template.to_string(),
+            uppercase: false,
+        }
+    }
+
+    pub fn uppercase(mut self) -> Self {
+        self.uppercase = true;
+        self
+    }
+
+    /// The parts of the template written before and after the heading's text.
+    pub(crate) fn affixes(&self) -> (&str, &str) {
+        match self.template.find("{}") {
+            Some(i) => (&self.template[..i], &self.template[i + 2..]),
+            None => (&self.template, ""),
+        }
+    }
+}
+
+/// Numbering of ordered list items.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Numbering {
+    /// `1.`, `2.`, `3.`
+    Decimal,
+    /// `a.`, `b.`, `c.`
+    LowerAlpha,
+    /// `A.`, `B.`, `C.`
+    UpperAlpha,
+    /// `i.`, `ii.`, `iii.`
+    LowerRoman,
+    /// `I.`, `II.`, `III.`
+    UpperRoman,
+}
+
+impl Numbering {
+    pub(crate) fn format(self, number: usize) -> String {
+        let label = match self {
+            Numbering::Decimal => number.to_string(),
+            Numbering::LowerAlpha => alpha(number),
+            Numbering::UpperAlpha => alpha(number).to_uppercase(),
+            Numbering::LowerRoman => roman(number),
+            Numbering::UpperRoman => roman(number).to_uppercase(),
+        };
+        format!("{}.", label)
+    }
+}
+
+/// Indentation of nested lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Indent {
+    /// A number of spaces per level.
+    Spaces(usize),
+    /// A number of non-breaking spaces per level, which Slack won't collapse.
+    NonBreakingSpaces(usize),
+}
+
+impl Indent {
+    pub(crate) fn render(self, depth: usize) -> String {
+        match self {
+            Indent::Spaces(width) => " ".repeat(width * depth),
+            Indent::NonBreakingSpaces(width) => "\u{a0}".repeat(width * depth),
+        }
+    }
+
+    /// Renders `width` columns of the same kind of space.
+    pub(crate) fn fill(self, width: usize) -> String {
+        match self {
+            Indent::Spaces(_) => " ".repeat(width),
+            Indent::NonBreakingSpaces(_) => "\u{a0}".repeat(width),
+        }
+    }
+}
+
+/// `1` is `a`, `26` is `z`, `27` is `aa`, and so on. Zero has no letter, so
+/// falls back to a digit.
+fn alpha(mut number: usize) -> String {
+    if number == 0 {
+        return "0".to_string();
+    }
+    let mut letters = Vec::new();
+    while number > 0 {
+        number -= 1;
+        letters.push((b'a' + (number % 26) as u8) as char);
+        number /= 26;
+    }
+    letters.iter().rev().collect()
+}
+
+/// Lowercase roman numerals. Zero has no numeral, so falls back to a digit.
+fn roman(mut number: usize) -> String {
+    const NUMERALS: [(usize, &str); 13] = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+    if number == 0 {
+        return "0".to_string();
+    }
+    let mut out = String::new();
+    for &(value, numeral) in NUMERALS.iter() {
+        while number >= value {
+            out.push_str(numeral);
+            number -= value;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_numbering() {
+        assert_eq!(Numbering::Decimal.format(12), "12.");
+        assert_eq!(Numbering::LowerAlpha.format(1), "a.");
+        assert_eq!(Numbering::LowerAlpha.format(28), "ab.");
+        assert_eq!(Numbering::UpperRoman.format(1994), "MCMXCIV.");
+        assert_eq!(Numbering::LowerRoman.format(4), "iv.");
+    }
+}
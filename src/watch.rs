@@ -0,0 +1,165 @@
+//! Watches Markdown files for changes, so that they can be converted again
+//! each time they're saved.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::batch::{self, absolute};
+
+/// How long writes have to stop for before a change is reported, since
+/// editors often save a file in several steps.
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Watches the files, directories and glob patterns taken by
+/// `batch::expand_inputs`.
+pub struct FileWatcher {
+    watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    /// Files named by the inputs.
+    files: Vec<PathBuf>,
+    /// Directories whose Markdown files are watched.
+    dirs: Vec<PathBuf>,
+    /// Paths whose changes are ignored, such as those of the output.
+    ignored: Vec<PathBuf>,
+    debounce: Duration,
+}
+
+impl FileWatcher {
+    pub fn new<S: AsRef<str>>(inputs: &[S]) -> io::Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = FileWatcher {
+            watcher: notify::recommended_watcher(sender).map_err(io::Error::other)?,
+            events,
+            files: Vec::new(),
+            dirs: Vec::new(),
+            ignored: Vec::new(),
+            debounce: DEFAULT_DEBOUNCE,
+        };
+        for input in inputs {
+            watcher.add(input.as_ref())?;
+        }
+        Ok(watcher)
+    }
+
+    pub fn debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    /// Ignores changes to `path`, or to anything under it if it's a directory.
+    pub fn ignore<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.ignored.push(absolute(path.as_ref()));
+        self
+    }
+
+    fn add(&mut self, input: &str) -> io::Result<()> {
+        let pattern = batch::is_pattern(input);
+        let path = if pattern {
+            // A pattern like `*.md` is relative to the current directory.
+            let base = batch::glob_base(input);
+            if base.as_os_str().is_empty() {
+                PathBuf::from(".")
+            } else {
+                base
+            }
+        } else {
+            PathBuf::from(input)
+        };
+        let path = absolute(&path);
+        // A pattern's base is always a directory, where new files matching the
+        // pattern can appear.
+        if pattern || path.is_dir() {
+            self.watch(&path, RecursiveMode::Recursive)?;
+            self.dirs.push(path);
+        } else {
+            // Editors often save by replacing the file, which would end a watch
+            // on the file itself, so its directory is watched instead.
+            let dir = path.parent().unwrap_or(&path);
+            self.watch(dir, RecursiveMode::NonRecursive)?;
+            self.files.push(path);
+        }
+        Ok(())
+    }
+
+    fn watch(&mut self, path: &Path, mode: RecursiveMode) -> io::Result<()> {
+        self.watcher.watch(path, mode).map_err(|e| match e.kind {
+            notify::ErrorKind::Io(e) => e,
+            _ => io::Error::other(format!("can't watch {}: {}", path.display(), e)),
+        })
+    }
+
+    /// Blocks until a watched file changes, and then until there have been no
+    /// more changes for the debounce period.
+    pub fn wait(&self) -> io::Result<()> {
+        loop {
+            let event = self.events.recv().map_err(io::Error::other)?;
+            if self.is_relevant(&event.map_err(io::Error::other)?) {
+                break;
+            }
+        }
+        loop {
+            match self.events.recv_timeout(self.debounce) {
+                Ok(_) => (),
+                Err(RecvTimeoutError::Timeout) => return Ok(()),
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(io::Error::other("file watcher stopped"))
+                }
+            }
+        }
+    }
+
+    fn is_relevant(&self, event: &Event) -> bool {
+        !matches!(event.kind, EventKind::Access(_))
+            && event.paths.iter().any(|path| self.is_watched(path))
+    }
+
+    fn is_watched(&self, path: &Path) -> bool {
+        if self.ignored.iter().any(|ignored| path.starts_with(ignored)) {
+            return false;
+        }
+        self.files.iter().any(|file| file == path)
+            || (batch::is_markdown(path) && self.dirs.iter().any(|dir| path.starts_with(dir)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_only_watched_files_are_relevant() {
+        let root = std::env::temp_dir().join(format!("slackify-watch-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("docs/out")).unwrap();
+        fs::write(root.join("notes.md"), "# Notes").unwrap();
+        let root = root.canonicalize().unwrap();
+
+        let watcher = FileWatcher::new(&[
+            root.join("notes.md").display().to_string(),
+            root.join("docs").display().to_string(),
+        ])
+        .unwrap()
+        .ignore(root.join("docs/out"));
+
+        assert!(watcher.is_watched(&root.join("notes.md")));
+        assert!(!watcher.is_watched(&root.join("other.md")));
+        assert!(!watcher.is_watched(&root.join(".notes.md.swp")));
+        assert!(watcher.is_watched(&root.join("docs/guide/setup.markdown")));
+        assert!(!watcher.is_watched(&root.join("docs/notes.txt")));
+        assert!(!watcher.is_watched(&root.join("docs/out/setup.md")));
+
+        let watcher = FileWatcher::new(&[root.join("docs/*.md").display().to_string()]).unwrap();
+        assert!(watcher.is_watched(&root.join("docs/new.md")));
+
+        let cwd = std::env::current_dir().unwrap().canonicalize().unwrap();
+        let watcher = FileWatcher::new(&["*.md"]).unwrap();
+        assert!(watcher.is_watched(&cwd.join("README.md")));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
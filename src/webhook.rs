@@ -0,0 +1,189 @@
+//! Posts converted messages to a Slack incoming webhook.
+
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+use serde_json::Value;
+
+/// Number of times a post is retried while Slack is rate limiting it.
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// How long to wait when a rate limited response doesn't say.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+/// The longest wait that a `Retry-After` header is allowed to ask for.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Settings sent along with a message, overriding those of the webhook.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PostOptions {
+    pub username: Option<String>,
+    /// An emoji, like `:robot_face:`, or the URL of an image.
+    pub icon: Option<String>,
+    pub channel: Option<String>,
+    pub unfurl_links: Option<bool>,
+    pub unfurl_media: Option<bool>,
+}
+
+impl PostOptions {
+    /// Adds the options to a payload, such as one from
+    /// `Converter::convert_to_payload`.
+    pub fn apply(&self, payload: &mut Value) {
+        let fields = match payload.as_object_mut() {
+            Some(fields) => fields,
+            None => return,
+        };
+        if let Some(username) = &self.username {
+            fields.insert("username".into(), username.as_str().into());
+        }
+        if let Some(icon) = &self.icon {
+            let key = if icon.starts_with(':') && icon.ends_with(':') {
+                "icon_emoji"
+            } else {
+                "icon_url"
+            };
+            fields.insert(key.into(), icon.as_str().into());
+        }
+        if let Some(channel) = &self.channel {
+            fields.insert("channel".into(), channel.as_str().into());
+        }
+        if let Some(unfurl_links) = self.unfurl_links {
+            fields.insert("unfurl_links".into(), unfurl_links.into());
+        }
+        if let Some(unfurl_media) = self.unfurl_media {
+            fields.insert("unfurl_media".into(), unfurl_media.into());
+        }
+    }
+}
+
+/// An incoming webhook, which messages are posted to as JSON.
+#[derive(Debug)]
+pub struct Webhook {
+    url: String,
+    max_retries: u32,
+    agent: ureq::Agent,
+}
+
+impl Webhook {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            max_retries: DEFAULT_MAX_RETRIES,
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(30))
+                .build(),
+        }
+    }
+
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Posts a payload. When Slack responds with `429 Too Many Requests` the
+    /// post is retried, after waiting as long as its `Retry-After` header asks.
+    pub fn post(&self, payload: &Value) -> io::Result<()> {
+        let body = payload.to_string();
+        let mut retries = 0;
+        loop {
+            let result = self
+                .agent
+                .post(&self.url)
+                .set("Content-Type", "application/json; charset=utf-8")
+                .send_string(&body);
+            match result {
+                Ok(_) => return Ok(()),
+                Err(ureq::Error::Status(429, response)) if retries < self.max_retries => {
+                    retries += 1;
+                    thread::sleep(retry_after(response.header("Retry-After")));
+                }
+                Err(ureq::Error::Status(status, response)) => {
+                    let text = response.into_string().unwrap_or_default();
+                    return Err(io::Error::other(format!(
+                        "webhook responded with {}: {}",
+                        status,
+                        text.trim()
+                    )));
+                }
+                Err(e) => return Err(io::Error::other(e)),
+            }
+        }
+    }
+}
+
+/// Parses a `Retry-After` header, which Slack gives in seconds.
+fn retry_after(header: Option<&str>) -> Duration {
+    header
+        .and_then(|secs| secs.trim().parse().ok())
+        .map_or(DEFAULT_RETRY_AFTER, Duration::from_secs)
+        .min(MAX_RETRY_AFTER)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tiny_http::{Header, Response, Server};
+
+    #[test]
+    fn test_post_retries_when_rate_limited() {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", server.server_addr().to_ip().unwrap());
+        let handle = thread::spawn(move || {
+            let mut bodies = Vec::new();
+            for status in [429, 200] {
+                let mut request = server.recv().unwrap();
+                let mut body = String::new();
+                request.as_reader().read_to_string(&mut body).unwrap();
+                bodies.push(body);
+                let retry_after = Header::from_bytes("Retry-After", "0").unwrap();
+                let response = Response::from_string("ok")
+                    .with_status_code(status)
+                    .with_header(retry_after);
+                request.respond(response).unwrap();
+            }
+            bodies
+        });
+
+        let mut payload = json!({ "text": "*Hi*" });
+        let options = PostOptions {
+            username: Some("deploy-bot".to_string()),
+            icon: Some(":rocket:".to_string()),
+            unfurl_links: Some(false),
+            ..PostOptions::default()
+        };
+        options.apply(&mut payload);
+        Webhook::new(&url).post(&payload).unwrap();
+
+        let bodies = handle.join().unwrap();
+        assert_eq!(bodies.len(), 2);
+        let sent: Value = serde_json::from_str(&bodies[1]).unwrap();
+        assert_eq!(
+            sent,
+            json!({
+                "text": "*Hi*",
+                "username": "deploy-bot",
+                "icon_emoji": ":rocket:",
+                "unfurl_links": false,
+            })
+        );
+    }
+
+    #[test]
+    fn test_post_fails_on_error_status() {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", server.server_addr().to_ip().unwrap());
+        let handle = thread::spawn(move || {
+            let request = server.recv().unwrap();
+            let response = Response::from_string("invalid_payload").with_status_code(400);
+            request.respond(response).unwrap();
+        });
+
+        let err = Webhook::new(&url).post(&json!({})).unwrap_err();
+        handle.join().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "webhook responded with 400: invalid_payload"
+        );
+    }
+}